@@ -0,0 +1,302 @@
+//! Node addresses covering IP sockets and the overlay networks gossiped in addrv2 messages.
+use std::{
+    fmt,
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Sha3_256};
+
+/// Domain suffix of Tor onion services.
+const ONION_SUFFIX: &str = ".onion";
+/// Domain suffix of I2P destinations.
+const I2P_SUFFIX: &str = ".b32.i2p";
+/// Version byte of Tor v3 onion addresses.
+const TOR_V3_VERSION: u8 = 3;
+/// Alphabet of the RFC 4648 base32 encoding, lowercase as used by Tor and I2P.
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Address of a node, either an IP socket or an overlay network address.
+///
+/// Displayed and serialized as `host:port`, e.g. `1.2.3.4:8233`, `[2001:db8::1]:8233`,
+/// `<56 characters>.onion:8233` or `<52 characters>.b32.i2p:0`. CJDNS addresses are IPv6
+/// addresses in `fc00::/8`, which are always treated as CJDNS rather than IP addresses.
+///
+/// IP and CJDNS addresses are created from a [`SocketAddr`], which drops IPv6 flow labels and
+/// scope ids, so that the same node always has the same address.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub enum NodeAddr {
+    /// An IP socket address.
+    Ip(IpSocketAddr),
+    /// A Tor v3 onion service, identified by its ed25519 public key.
+    TorV3 { pubkey: [u8; 32], port: u16 },
+    /// An I2P destination, identified by the SHA-256 hash of the destination.
+    I2p { hash: [u8; 32], port: u16 },
+    /// A CJDNS address.
+    Cjdns(CjdnsSocketAddr),
+}
+
+/// An IP socket address outside of `fc00::/8`, without IPv6 flow label or scope id.
+///
+/// Only constructed by converting a [`SocketAddr`] into a [`NodeAddr`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub struct IpSocketAddr(SocketAddr);
+
+impl IpSocketAddr {
+    /// Returns the socket address.
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.0
+    }
+}
+
+/// A CJDNS socket address, within `fc00::/8` and without IPv6 flow label or scope id.
+///
+/// Only constructed by converting a [`SocketAddr`] into a [`NodeAddr`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub struct CjdnsSocketAddr(SocketAddrV6);
+
+impl CjdnsSocketAddr {
+    /// Returns the CJDNS address.
+    pub fn ip(&self) -> Ipv6Addr {
+        *self.0.ip()
+    }
+
+    /// Returns the port.
+    pub fn port(&self) -> u16 {
+        self.0.port()
+    }
+}
+
+impl NodeAddr {
+    /// Returns the port of the address.
+    pub fn port(&self) -> u16 {
+        match self {
+            NodeAddr::Ip(addr) => addr.0.port(),
+            NodeAddr::TorV3 { port, .. } | NodeAddr::I2p { port, .. } => *port,
+            NodeAddr::Cjdns(addr) => addr.port(),
+        }
+    }
+
+    /// Returns the socket address of an IP node, `None` for overlay network addresses.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            NodeAddr::Ip(addr) => Some(addr.0),
+            _ => None,
+        }
+    }
+
+    /// Returns the IP address of an IP node, `None` for overlay network addresses.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.socket_addr().map(|addr| addr.ip())
+    }
+
+    /// Returns `true` for an IP socket address.
+    pub fn is_ip(&self) -> bool {
+        matches!(self, NodeAddr::Ip(_))
+    }
+}
+
+impl From<SocketAddr> for NodeAddr {
+    fn from(addr: SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V6(v6) => {
+                let v6 = SocketAddrV6::new(*v6.ip(), v6.port(), 0, 0);
+                if v6.ip().octets()[0] == 0xfc {
+                    NodeAddr::Cjdns(CjdnsSocketAddr(v6))
+                } else {
+                    NodeAddr::Ip(IpSocketAddr(v6.into()))
+                }
+            }
+            addr => NodeAddr::Ip(IpSocketAddr(addr)),
+        }
+    }
+}
+
+impl<I: Into<IpAddr>> From<(I, u16)> for NodeAddr {
+    fn from((ip, port): (I, u16)) -> Self {
+        SocketAddr::from((ip, port)).into()
+    }
+}
+
+impl fmt::Display for NodeAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeAddr::Ip(addr) => write!(f, "{}", addr.0),
+            NodeAddr::TorV3 { pubkey, port } => {
+                let mut bytes = pubkey.to_vec();
+                bytes.extend_from_slice(&onion_checksum(pubkey));
+                bytes.push(TOR_V3_VERSION);
+                write!(f, "{}{ONION_SUFFIX}:{port}", base32_encode(&bytes))
+            }
+            NodeAddr::I2p { hash, port } => {
+                write!(f, "{}{I2P_SUFFIX}:{port}", base32_encode(hash))
+            }
+            NodeAddr::Cjdns(addr) => write!(f, "{}", addr.0),
+        }
+    }
+}
+
+impl FromStr for NodeAddr {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = input.parse::<SocketAddr>() {
+            return Ok(addr.into());
+        }
+
+        let invalid = || format!("invalid node address: {input}");
+        let (host, port) = input.rsplit_once(':').ok_or_else(invalid)?;
+        let port: u16 = port.parse().map_err(|_| invalid())?;
+        let host = host.to_ascii_lowercase();
+
+        if let Some(name) = host.strip_suffix(I2P_SUFFIX) {
+            let hash = base32_decode(name)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(invalid)?;
+            return Ok(NodeAddr::I2p { hash, port });
+        }
+
+        if let Some(name) = host.strip_suffix(ONION_SUFFIX) {
+            let bytes = base32_decode(name)
+                .filter(|bytes| bytes.len() == 35)
+                .ok_or_else(invalid)?;
+            let mut pubkey = [0; 32];
+            pubkey.copy_from_slice(&bytes[..32]);
+
+            if bytes[34] != TOR_V3_VERSION || bytes[32..34] != onion_checksum(&pubkey) {
+                return Err(format!("invalid onion v3 address: {input}"));
+            }
+            return Ok(NodeAddr::TorV3 { pubkey, port });
+        }
+
+        Err(invalid())
+    }
+}
+
+impl Serialize for NodeAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeAddr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let addr = String::deserialize(deserializer)?;
+        addr.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Computes the checksum of a Tor v3 onion address, as defined in the rend-spec-v3.
+fn onion_checksum(pubkey: &[u8; 32]) -> [u8; 2] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b".onion checksum");
+    hasher.update(pubkey);
+    hasher.update([TOR_V3_VERSION]);
+    let digest = hasher.finalize();
+
+    [digest[0], digest[1]]
+}
+
+/// Encodes bytes as unpadded, lowercase base32.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u16, 0);
+
+    for byte in bytes {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+
+    encoded
+}
+
+/// Decodes unpadded, lowercase base32, `None` if the input isn't canonically encoded.
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(input.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u16, 0);
+
+    for c in input.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&symbol| symbol == c)? as u16;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+
+    // Leftover bits must be zero padding of the last character.
+    (bits < 5 && buffer & ((1 << bits) - 1) == 0).then_some(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_round_trip_addresses() {
+        // Onion address of the Tor Project's website.
+        let onion = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion:443";
+        let i2p = "ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p:0";
+
+        for input in [
+            "1.2.3.4:8233",
+            "[2001:db8::1]:8233",
+            "[fc00::1]:8233",
+            onion,
+            i2p,
+        ] {
+            let addr: NodeAddr = input.parse().unwrap();
+            assert_eq!(addr.to_string(), input);
+
+            let json = serde_json::to_string(&addr).unwrap();
+            assert_eq!(serde_json::from_str::<NodeAddr>(&json).unwrap(), addr);
+        }
+
+        assert!(matches!(
+            onion.parse(),
+            Ok(NodeAddr::TorV3 { port: 443, .. })
+        ));
+        assert!(matches!(i2p.parse(), Ok(NodeAddr::I2p { port: 0, .. })));
+        assert!(matches!("[fc00::1]:1".parse(), Ok(NodeAddr::Cjdns(_))));
+        assert_eq!(
+            "1.2.3.4:8233".parse::<NodeAddr>().unwrap().ip(),
+            Some(IpAddr::from([1, 2, 3, 4]))
+        );
+    }
+
+    #[test]
+    fn should_canonicalise_socket_addresses() {
+        let scoped = SocketAddrV6::new("2001:db8::1".parse().unwrap(), 8233, 7, 3);
+        let addr = NodeAddr::from(SocketAddr::V6(scoped));
+        assert_eq!(addr, "[2001:db8::1]:8233".parse().unwrap());
+        assert_eq!(addr.to_string().parse::<NodeAddr>().unwrap(), addr);
+
+        let cjdns = NodeAddr::from(("fc00::1".parse::<Ipv6Addr>().unwrap(), 8233));
+        assert!(!cjdns.is_ip());
+        assert_eq!(cjdns, "[fc00::1]:8233".parse().unwrap());
+    }
+
+    #[test]
+    fn should_reject_invalid_addresses() {
+        // Last character altered, which breaks the checksum.
+        let corrupted = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wia.onion:443";
+
+        assert!(corrupted.parse::<NodeAddr>().is_err());
+        assert!("abc.onion:443".parse::<NodeAddr>().is_err());
+        assert!(
+            "ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p"
+                .parse::<NodeAddr>()
+                .is_err()
+        );
+        assert!("example.com:8233".parse::<NodeAddr>().is_err());
+    }
+}
@@ -0,0 +1,390 @@
+//! Keyed pseudonymisation of node addresses for publishing crawl results.
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    address::NodeAddr,
+    graph::adjacency_sets,
+    record::NodeRecord,
+    summary::{NetworkSummary, NetworkType},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How IP addresses are mapped to pseudonyms.
+///
+/// Onion and I2P keys are always replaced by keyed hashes, CJDNS addresses are mapped like
+/// IPv6 addresses but stay within `fc00::/8`.
+#[derive(Default, PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AddressMapping {
+    /// Each address is replaced by a keyed hash of it, truncated to the address length.
+    ///
+    /// Related addresses get unrelated pseudonyms. Distinct addresses can, in rare cases,
+    /// map to the same pseudonym.
+    #[default]
+    Hashed,
+    /// Addresses sharing an `n`-bit prefix get pseudonyms sharing an `n`-bit prefix
+    /// (Crypto-PAn style), so subnet analysis still works on the anonymised data.
+    ///
+    /// The mapping is a bijection within each address family.
+    PrefixPreserving,
+}
+
+/// Anonymisation options.
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct AnonymiseConfig {
+    /// How IP addresses are mapped.
+    pub mapping: AddressMapping,
+    /// Replaces all ports with `0`.
+    ///
+    /// Nodes sharing an IP address are then merged into a single node, along with their
+    /// connections and records. Without node records, versions and user agents still count
+    /// the merged nodes separately.
+    pub drop_ports: bool,
+}
+
+/// Maps node addresses to pseudonyms, deterministically for a given secret key.
+#[derive(Clone)]
+pub struct Anonymiser {
+    mac: HmacSha256,
+    config: AnonymiseConfig,
+}
+
+impl Anonymiser {
+    pub fn new(key: &[u8], config: AnonymiseConfig) -> Self {
+        Self {
+            mac: HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            config,
+        }
+    }
+
+    /// Returns the pseudonym of the IP address, which belongs to the same address family.
+    ///
+    /// IPv4-mapped IPv6 addresses are mapped like the IPv4 addresses they embed.
+    pub fn anonymise_ip(&self, ip: IpAddr) -> IpAddr {
+        if let IpAddr::V6(mapped) = ip {
+            if let Some(ip) = mapped.to_ipv4_mapped() {
+                let bits = self.anonymise_bits(4, u32::from(ip).into(), 32);
+                return IpAddr::V6(Ipv4Addr::from(bits as u32).to_ipv6_mapped());
+            }
+        }
+
+        match ip {
+            IpAddr::V4(ip) => {
+                let bits = self.anonymise_bits(4, u32::from(ip).into(), 32);
+                IpAddr::V4(Ipv4Addr::from(bits as u32))
+            }
+            IpAddr::V6(ip) => {
+                let bits = self.avoid_cjdns(self.anonymise_bits(6, u128::from(ip), 128));
+                IpAddr::V6(Ipv6Addr::from(bits))
+            }
+        }
+    }
+
+    /// Returns the pseudonym of the node address, which belongs to the same address family.
+    pub fn anonymise_addr(&self, addr: NodeAddr) -> NodeAddr {
+        let port = if self.config.drop_ports {
+            0
+        } else {
+            addr.port()
+        };
+
+        match addr {
+            NodeAddr::Ip(addr) => {
+                SocketAddr::new(self.anonymise_ip(addr.socket_addr().ip()), port).into()
+            }
+            NodeAddr::TorV3 { pubkey, .. } => NodeAddr::TorV3 {
+                pubkey: self.prf(&[b"onion", &pubkey]),
+                port,
+            },
+            NodeAddr::I2p { hash, .. } => NodeAddr::I2p {
+                hash: self.prf(&[b"i2p", &hash]),
+                port,
+            },
+            NodeAddr::Cjdns(addr) => {
+                let bits = self.anonymise_bits(b'c', u128::from(addr.ip()), 128);
+                NodeAddr::from((
+                    Ipv6Addr::from((bits & (u128::MAX >> 8)) | (0xfc << 120)),
+                    port,
+                ))
+            }
+        }
+    }
+
+    /// Returns a copy of the summary with all node addresses replaced by their pseudonyms.
+    ///
+    /// Topology, versions, user agents, network types and the rest of the node records are
+    /// kept intact, unless nodes are merged because their ports were dropped.
+    pub fn anonymise(&self, summary: &NetworkSummary) -> NetworkSummary {
+        let anonymised = NetworkSummary {
+            node_addrs: summary
+                .node_addrs
+                .iter()
+                .map(|addr| self.anonymise_addr(*addr))
+                .collect(),
+            node_records: summary
+                .node_records
+                .iter()
+                .map(|record| NodeRecord {
+                    addr: self.anonymise_addr(record.addr),
+                    ..record.clone()
+                })
+                .collect(),
+            ..summary.clone()
+        };
+
+        if self.config.drop_ports {
+            merge_duplicate_nodes(anonymised)
+        } else {
+            anonymised
+        }
+    }
+
+    /// Maps the lowest `len` bits of `bits`, `family` separates the IPv4 and IPv6 domains.
+    fn anonymise_bits(&self, family: u8, bits: u128, len: u32) -> u128 {
+        match self.config.mapping {
+            AddressMapping::Hashed => {
+                let digest = self.prf(&[&[family, 0], &bits.to_be_bytes()]);
+                let mut hash = [0u8; 16];
+                hash.copy_from_slice(&digest[..16]);
+                u128::from_be_bytes(hash) >> (128 - len)
+            }
+            AddressMapping::PrefixPreserving => {
+                // Each bit is flipped by a pseudorandom function of the bits preceding it.
+                let mut result = 0;
+                for position in 0..len {
+                    let prefix = bits.checked_shr(len - position).unwrap_or(0);
+                    let digest = self.prf(&[&[family, 1, position as u8], &prefix.to_be_bytes()]);
+                    let flip = u128::from(digest[0] >> 7);
+                    let bit = (bits >> (len - position - 1)) & 1;
+                    result = (result << 1) | (bit ^ flip);
+                }
+                result
+            }
+        }
+    }
+
+    /// Keeps anonymised IPv6 addresses out of `fc00::/8`, which is reserved for CJDNS.
+    fn avoid_cjdns(&self, bits: u128) -> u128 {
+        if bits >> 120 != 0xfc {
+            return bits;
+        }
+
+        // No IP address is in `fc00::/8`, so in the prefix-preserving mapping the prefix which
+        // `fc00::/8` itself maps to is free, and taking it keeps the mapping a bijection.
+        let free = self.anonymise_bits(6, 0xfc << 120, 128) >> 120;
+        let free = if free == 0xfc { 0xfd } else { free };
+        (bits & (u128::MAX >> 8)) | (free << 120)
+    }
+
+    fn prf(&self, parts: &[&[u8]]) -> [u8; 32] {
+        let mut mac = self.mac.clone();
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize().into_bytes().into()
+    }
+}
+
+/// Merges the nodes which share an address, along with their connections and records.
+fn merge_duplicate_nodes(mut summary: NetworkSummary) -> NetworkSummary {
+    // Map: Address -> index of the merged node, in the order of first appearance.
+    let mut positions: HashMap<NodeAddr, usize> = HashMap::new();
+    let merged_indices: Vec<usize> = summary
+        .node_addrs
+        .iter()
+        .map(|addr| {
+            let next = positions.len();
+            *positions.entry(*addr).or_insert(next)
+        })
+        .collect();
+    let num_nodes = positions.len();
+    if num_nodes == summary.node_addrs.len() {
+        return summary;
+    }
+
+    let has_records = summary.has_node_records();
+    let mut node_addrs = Vec::with_capacity(num_nodes);
+    let mut node_network_types: Vec<NetworkType> = Vec::with_capacity(num_nodes);
+    let mut node_records: Vec<NodeRecord> = Vec::with_capacity(num_nodes);
+    let mut neighbours = vec![BTreeSet::new(); num_nodes];
+
+    for (node, &merged) in merged_indices.iter().enumerate() {
+        let network_type = summary.node_network_types.get(node);
+        if merged == node_addrs.len() {
+            node_addrs.push(summary.node_addrs[node]);
+            node_network_types.push(network_type.cloned().unwrap_or_default());
+            if has_records {
+                node_records.push(summary.node_records[node].clone());
+            }
+        } else {
+            if node_network_types[merged] == NetworkType::Unknown {
+                if let Some(network_type) = network_type {
+                    node_network_types[merged] = network_type.clone();
+                }
+            }
+            if has_records {
+                node_records[merged].merge(&summary.node_records[node]);
+            }
+        }
+
+        for neighbour in summary.nodes_indices.get(node).into_iter().flatten() {
+            match merged_indices.get(*neighbour) {
+                Some(&other) if other != merged => {
+                    neighbours[merged].insert(other);
+                    neighbours[other].insert(merged);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let old_connections = adjacency_sets(&summary.nodes_indices)
+        .iter()
+        .map(|set| set.len())
+        .sum::<usize>()
+        / 2;
+    let connections = neighbours.iter().map(BTreeSet::len).sum::<usize>() / 2;
+    summary.num_known_connections = summary
+        .num_known_connections
+        .saturating_sub(old_connections.saturating_sub(connections))
+        .max(connections);
+    summary.num_known_nodes = summary
+        .num_known_nodes
+        .saturating_sub(summary.node_addrs.len() - num_nodes)
+        .max(num_nodes);
+    summary.num_good_nodes = num_nodes;
+
+    summary.node_addrs = node_addrs;
+    summary.node_network_types = node_network_types;
+    summary.nodes_indices = neighbours
+        .into_iter()
+        .map(|set| set.into_iter().collect())
+        .collect();
+    if has_records {
+        summary.node_records = node_records;
+        summary.derive_from_records();
+    }
+
+    summary
+}
+
+impl NetworkSummary {
+    /// Returns a copy of the summary with node addresses replaced by keyed pseudonyms.
+    ///
+    /// The same key always produces the same pseudonyms, so anonymised crawls stay linkable.
+    pub fn anonymised(&self, key: &[u8], config: AnonymiseConfig) -> NetworkSummary {
+        Anonymiser::new(key, config).anonymise(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::summary::NetworkType;
+
+    fn summary() -> NetworkSummary {
+        NetworkSummary {
+            num_known_nodes: 3,
+            num_good_nodes: 3,
+            node_addrs: vec![
+                "1.2.3.4:8233".parse().unwrap(),
+                "1.2.3.200:8234".parse().unwrap(),
+                "[2001:db8::1]:8233".parse().unwrap(),
+            ],
+            node_network_types: vec![NetworkType::Zcash; 3],
+            nodes_indices: vec![vec![1, 2], vec![0], vec![0]],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_anonymise_deterministically() {
+        let config = AnonymiseConfig::default();
+        let first = summary().anonymised(b"secret", config.clone());
+        let second = summary().anonymised(b"secret", config.clone());
+        let other_key = summary().anonymised(b"other", config);
+
+        assert_eq!(first.node_addrs, second.node_addrs);
+        assert_ne!(first.node_addrs, other_key.node_addrs);
+        assert_ne!(first.node_addrs, summary().node_addrs);
+        assert_eq!(first.nodes_indices, summary().nodes_indices);
+        assert_eq!(first.node_network_types, summary().node_network_types);
+        assert!(first.node_addrs[2].ip().unwrap().is_ipv6());
+        assert_eq!(first.node_addrs[1].port(), 8234);
+    }
+
+    #[test]
+    fn should_preserve_prefixes() {
+        let config = AnonymiseConfig {
+            mapping: AddressMapping::PrefixPreserving,
+            drop_ports: true,
+        };
+        let anonymised = summary().anonymised(b"secret", config);
+
+        let (Some(IpAddr::V4(a)), Some(IpAddr::V4(b))) =
+            (anonymised.node_addrs[0].ip(), anonymised.node_addrs[1].ip())
+        else {
+            panic!("address family changed");
+        };
+        // The original addresses share a 24-bit prefix and differ in the first bit after it.
+        assert_eq!(u32::from(a) >> 8, u32::from(b) >> 8);
+        assert_ne!(u32::from(a) >> 7, u32::from(b) >> 7);
+        assert_ne!(a, Ipv4Addr::new(1, 2, 3, 4));
+        assert!(anonymised.node_addrs.iter().all(|addr| addr.port() == 0));
+    }
+
+    #[test]
+    fn should_merge_nodes_sharing_an_address() {
+        let mut summary = summary();
+        // A second node on the IPv6 address, connected to node 1.
+        summary
+            .node_addrs
+            .push("[2001:db8::1]:9000".parse().unwrap());
+        summary.node_network_types.push(NetworkType::Zcash);
+        summary.nodes_indices = vec![vec![1, 2], vec![0, 3], vec![0], vec![1]];
+        summary.num_known_nodes = 4;
+        summary.num_good_nodes = 4;
+        summary.num_known_connections = 3;
+        let config = AnonymiseConfig {
+            drop_ports: true,
+            ..Default::default()
+        };
+
+        let anonymised = summary.anonymised(b"secret", config);
+
+        assert_eq!(anonymised.node_addrs.len(), 3);
+        assert_eq!(
+            anonymised.nodes_indices,
+            vec![vec![1, 2], vec![0, 2], vec![0, 1]]
+        );
+        assert_eq!(anonymised.num_known_connections, 3);
+        assert!(anonymised.validate().is_empty());
+    }
+
+    #[test]
+    fn should_map_ipv4_mapped_addresses_as_ipv4() {
+        let anonymiser = Anonymiser::new(
+            b"secret",
+            AnonymiseConfig {
+                mapping: AddressMapping::PrefixPreserving,
+                ..Default::default()
+            },
+        );
+        let ip = Ipv4Addr::new(1, 2, 3, 4);
+
+        let IpAddr::V6(mapped) = anonymiser.anonymise_ip(IpAddr::V6(ip.to_ipv6_mapped())) else {
+            panic!("address family changed");
+        };
+        assert_eq!(
+            mapped.to_ipv4_mapped().map(IpAddr::V4),
+            Some(anonymiser.anonymise_ip(IpAddr::V4(ip)))
+        );
+    }
+}
@@ -0,0 +1,814 @@
+//! Compact binary encoding of network summaries, readable and writable as a stream.
+//!
+//! The encoding starts with [`MAGIC`] and the format version, followed by the summary header,
+//! one entry per node and finally the connections of each node. Integers are LEB128 varints,
+//! connections are delta-encoded and strings are interned: the first occurrence of a string is
+//! written inline, later ones refer to it by its index.
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    iter,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    address::NodeAddr,
+    record::NodeRecord,
+    summary::{NetworkSummary, NetworkType},
+};
+
+/// Bytes every encoded summary starts with.
+pub const MAGIC: [u8; 4] = *b"ZCNS";
+/// Version of the encoding written by this crate.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Header flag set if nodes carry their network type.
+const FLAG_NETWORK_TYPES: u8 = 1;
+/// Header flag set if nodes carry their record.
+const FLAG_RECORDS: u8 = 1 << 1;
+
+const ADDR_IPV4: u8 = 0;
+const ADDR_IPV6: u8 = 1;
+const ADDR_TOR_V3: u8 = 2;
+const ADDR_I2P: u8 = 3;
+const ADDR_CJDNS: u8 = 4;
+
+/// Presence bits of the optional record fields.
+const RECORD_USER_AGENT: u8 = 1;
+const RECORD_PROTOCOL_VERSION: u8 = 1 << 1;
+const RECORD_SERVICES: u8 = 1 << 2;
+const RECORD_START_HEIGHT: u8 = 1 << 3;
+const RECORD_HANDSHAKE_LATENCY: u8 = 1 << 4;
+const RECORD_FIRST_SEEN: u8 = 1 << 5;
+const RECORD_LAST_SEEN: u8 = 1 << 6;
+
+/// Upper bound of capacities preallocated from counts read from the stream.
+const MAX_PREALLOCATION: usize = 1024;
+
+/// Everything in a summary apart from its nodes and connections.
+#[derive(Default, Clone, Debug)]
+pub struct SummaryHeader {
+    /// Total number of nodes discovered.
+    pub num_known_nodes: usize,
+    /// Number of nodes that a crawler was able to connect to.
+    pub num_good_nodes: usize,
+    /// Total number of known connections as reported by peers.
+    pub num_known_connections: usize,
+    /// Number of all protocol versions discovered.
+    pub num_versions: usize,
+    /// Map: Version number -> number of nodes that reported this version.
+    pub protocol_versions: HashMap<u32, usize>,
+    /// Nodes' software versions.
+    pub user_agents: HashMap<String, usize>,
+    /// Crawler's runtime.
+    pub crawler_runtime: Duration,
+    /// Number of node entries, which is also the number of adjacency lists.
+    pub num_nodes: usize,
+    /// Whether the node entries carry network types.
+    pub has_network_types: bool,
+    /// Whether the node entries carry records.
+    pub has_records: bool,
+}
+
+impl SummaryHeader {
+    /// Creates the header of the given summary.
+    pub fn new(summary: &NetworkSummary) -> Self {
+        Self {
+            num_known_nodes: summary.num_known_nodes,
+            num_good_nodes: summary.num_good_nodes,
+            num_known_connections: summary.num_known_connections,
+            num_versions: summary.num_versions,
+            protocol_versions: summary.protocol_versions.clone(),
+            user_agents: summary.user_agents.clone(),
+            crawler_runtime: summary.crawler_runtime,
+            num_nodes: summary.node_addrs.len(),
+            has_network_types: !summary.node_network_types.is_empty(),
+            has_records: !summary.node_records.is_empty(),
+        }
+    }
+}
+
+/// A single node of an encoded summary.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct NodeEntry {
+    pub addr: NodeAddr,
+    /// Present if the header says so.
+    pub network_type: Option<NetworkType>,
+    /// Present if the header says so, its address must match the node's.
+    pub record: Option<NodeRecord>,
+}
+
+/// Writes a summary piece by piece: the header, then every node, then every adjacency list.
+pub struct SummaryWriter<W: Write> {
+    writer: W,
+    header: SummaryHeader,
+    strings: HashMap<String, u64>,
+    nodes_written: usize,
+    lists_written: usize,
+}
+
+impl<W: Write> SummaryWriter<W> {
+    /// Starts the encoding by writing the format version and the header.
+    pub fn new(writer: W, header: SummaryHeader) -> io::Result<Self> {
+        let mut this = Self {
+            writer,
+            header,
+            strings: HashMap::new(),
+            nodes_written: 0,
+            lists_written: 0,
+        };
+
+        let mut flags = 0;
+        if this.header.has_network_types {
+            flags |= FLAG_NETWORK_TYPES;
+        }
+        if this.header.has_records {
+            flags |= FLAG_RECORDS;
+        }
+        this.writer.write_all(&MAGIC)?;
+        this.writer.write_all(&[FORMAT_VERSION, flags])?;
+
+        for value in [
+            this.header.num_nodes,
+            this.header.num_known_nodes,
+            this.header.num_good_nodes,
+            this.header.num_known_connections,
+            this.header.num_versions,
+        ] {
+            write_varint(&mut this.writer, value as u64)?;
+        }
+        write_duration(&mut this.writer, this.header.crawler_runtime)?;
+
+        let mut versions: Vec<(u32, usize)> = this
+            .header
+            .protocol_versions
+            .iter()
+            .map(|(version, count)| (*version, *count))
+            .collect();
+        versions.sort_unstable();
+        write_varint(&mut this.writer, versions.len() as u64)?;
+        for (version, count) in versions {
+            write_varint(&mut this.writer, version.into())?;
+            write_varint(&mut this.writer, count as u64)?;
+        }
+
+        let mut user_agents: Vec<(String, usize)> = this
+            .header
+            .user_agents
+            .iter()
+            .map(|(user_agent, count)| (user_agent.clone(), *count))
+            .collect();
+        user_agents.sort_unstable();
+        write_varint(&mut this.writer, user_agents.len() as u64)?;
+        for (user_agent, count) in user_agents {
+            this.write_str(&user_agent)?;
+            write_varint(&mut this.writer, count as u64)?;
+        }
+
+        Ok(this)
+    }
+
+    /// Writes the next node, all nodes must be written before any connections.
+    pub fn write_node(&mut self, node: &NodeEntry) -> io::Result<()> {
+        if self.nodes_written == self.header.num_nodes {
+            return Err(misuse("all nodes were already written"));
+        }
+        if node.network_type.is_some() != self.header.has_network_types
+            || node.record.is_some() != self.header.has_records
+        {
+            return Err(misuse("node entry doesn't match the header"));
+        }
+        if node
+            .record
+            .as_ref()
+            .is_some_and(|record| record.addr != node.addr)
+        {
+            return Err(misuse("record address doesn't match the node"));
+        }
+
+        write_addr(&mut self.writer, &node.addr)?;
+        if let Some(network_type) = &node.network_type {
+            self.write_str(network_type.name())?;
+        }
+        if let Some(record) = &node.record {
+            self.write_record(record)?;
+        }
+
+        self.nodes_written += 1;
+        Ok(())
+    }
+
+    /// Writes the connections of the next node, every peer must be the index of a node.
+    pub fn write_peers(&mut self, peers: &[usize]) -> io::Result<()> {
+        if self.nodes_written < self.header.num_nodes {
+            return Err(misuse("nodes must be written before connections"));
+        }
+        if self.lists_written == self.header.num_nodes {
+            return Err(misuse("all connections were already written"));
+        }
+        if peers.iter().any(|peer| *peer >= self.header.num_nodes) {
+            return Err(misuse("peer index out of range"));
+        }
+
+        write_varint(&mut self.writer, peers.len() as u64)?;
+        let mut previous = self.lists_written as u64;
+        for peer in peers {
+            let delta = (*peer as u64).wrapping_sub(previous) as i64;
+            write_varint(&mut self.writer, zigzag_encode(delta))?;
+            previous = *peer as u64;
+        }
+
+        self.lists_written += 1;
+        Ok(())
+    }
+
+    /// Completes the encoding and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.lists_written < self.header.num_nodes {
+            return Err(misuse("not all connections were written"));
+        }
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn write_str(&mut self, string: &str) -> io::Result<()> {
+        if let Some(id) = self.strings.get(string) {
+            return write_varint(&mut self.writer, id + 1);
+        }
+
+        write_varint(&mut self.writer, 0)?;
+        write_varint(&mut self.writer, string.len() as u64)?;
+        self.writer.write_all(string.as_bytes())?;
+        self.strings
+            .insert(string.to_owned(), self.strings.len() as u64);
+
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &NodeRecord) -> io::Result<()> {
+        let mut fields = 0;
+        for (present, bit) in [
+            (record.user_agent.is_some(), RECORD_USER_AGENT),
+            (record.protocol_version.is_some(), RECORD_PROTOCOL_VERSION),
+            (record.services.is_some(), RECORD_SERVICES),
+            (record.start_height.is_some(), RECORD_START_HEIGHT),
+            (record.handshake_latency.is_some(), RECORD_HANDSHAKE_LATENCY),
+            (record.first_seen.is_some(), RECORD_FIRST_SEEN),
+            (record.last_seen.is_some(), RECORD_LAST_SEEN),
+        ] {
+            if present {
+                fields |= bit;
+            }
+        }
+
+        self.write_str(record.network_type.name())?;
+        self.writer
+            .write_all(&[fields, record.connection_failures])?;
+        if let Some(user_agent) = &record.user_agent {
+            self.write_str(user_agent)?;
+        }
+        for value in [
+            record.protocol_version.map(u64::from),
+            record.services,
+            record.start_height,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            write_varint(&mut self.writer, value)?;
+        }
+        if let Some(latency) = record.handshake_latency {
+            write_duration(&mut self.writer, latency)?;
+        }
+        for time in [record.first_seen, record.last_seen].into_iter().flatten() {
+            let since_epoch = time
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| misuse("timestamp before epoch"))?;
+            write_duration(&mut self.writer, since_epoch)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a summary piece by piece, without materialising it as a whole.
+pub struct SummaryReader<R: Read> {
+    reader: R,
+    header: SummaryHeader,
+    strings: Vec<String>,
+    nodes_read: usize,
+    lists_read: usize,
+}
+
+impl<R: Read> SummaryReader<R> {
+    /// Checks the format version and reads the header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut preamble = [0; 6];
+        reader.read_exact(&mut preamble)?;
+        if preamble[..4] != MAGIC {
+            return Err(invalid("not an encoded network summary"));
+        }
+        if preamble[4] != FORMAT_VERSION {
+            return Err(invalid(format!(
+                "unsupported format version: {}",
+                preamble[4]
+            )));
+        }
+        let flags = preamble[5];
+
+        let mut this = Self {
+            reader,
+            header: SummaryHeader {
+                has_network_types: flags & FLAG_NETWORK_TYPES != 0,
+                has_records: flags & FLAG_RECORDS != 0,
+                ..Default::default()
+            },
+            strings: Vec::new(),
+            nodes_read: 0,
+            lists_read: 0,
+        };
+
+        this.header.num_nodes = read_usize(&mut this.reader)?;
+        this.header.num_known_nodes = read_usize(&mut this.reader)?;
+        this.header.num_good_nodes = read_usize(&mut this.reader)?;
+        this.header.num_known_connections = read_usize(&mut this.reader)?;
+        this.header.num_versions = read_usize(&mut this.reader)?;
+        this.header.crawler_runtime = read_duration(&mut this.reader)?;
+
+        for _ in 0..read_usize(&mut this.reader)? {
+            let version = u32::try_from(read_varint(&mut this.reader)?)
+                .map_err(|_| invalid("protocol version out of range"))?;
+            let count = read_usize(&mut this.reader)?;
+            this.header.protocol_versions.insert(version, count);
+        }
+        for _ in 0..read_usize(&mut this.reader)? {
+            let user_agent = this.read_str()?;
+            let count = read_usize(&mut this.reader)?;
+            this.header.user_agents.insert(user_agent, count);
+        }
+
+        Ok(this)
+    }
+
+    /// Returns the header of the summary.
+    pub fn header(&self) -> &SummaryHeader {
+        &self.header
+    }
+
+    /// Reads the next node, `None` once all nodes were read.
+    pub fn read_node(&mut self) -> io::Result<Option<NodeEntry>> {
+        if self.nodes_read == self.header.num_nodes {
+            return Ok(None);
+        }
+
+        let addr = read_addr(&mut self.reader)?;
+        // Custom network names never match known ones, so they are decoded as they were written.
+        let network_type = if self.header.has_network_types {
+            Some(NetworkType::custom(&self.read_str()?))
+        } else {
+            None
+        };
+        let record = if self.header.has_records {
+            Some(self.read_record(addr)?)
+        } else {
+            None
+        };
+
+        self.nodes_read += 1;
+        Ok(Some(NodeEntry {
+            addr,
+            network_type,
+            record,
+        }))
+    }
+
+    /// Reads the connections of the next node, `None` once all of them were read.
+    ///
+    /// All nodes must be read first.
+    pub fn read_peers(&mut self) -> io::Result<Option<Vec<usize>>> {
+        if self.nodes_read < self.header.num_nodes {
+            return Err(misuse("nodes must be read before connections"));
+        }
+        if self.lists_read == self.header.num_nodes {
+            return Ok(None);
+        }
+
+        let len = read_usize(&mut self.reader)?;
+        let mut peers = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+        let mut previous = self.lists_read as u64;
+        for _ in 0..len {
+            let delta = zigzag_decode(read_varint(&mut self.reader)?);
+            previous = previous.wrapping_add(delta as u64);
+            match usize::try_from(previous) {
+                Ok(peer) if peer < self.header.num_nodes => peers.push(peer),
+                _ => return Err(invalid("index out of range")),
+            }
+        }
+
+        self.lists_read += 1;
+        Ok(Some(peers))
+    }
+
+    /// Iterates over the remaining nodes.
+    pub fn nodes(&mut self) -> impl Iterator<Item = io::Result<NodeEntry>> + '_ {
+        iter::from_fn(move || self.read_node().transpose())
+    }
+
+    /// Iterates over the remaining connections as `(node, peer)` pairs, in the order they are
+    /// stored in `nodes_indices`. All nodes must be read first.
+    pub fn edges(&mut self) -> impl Iterator<Item = io::Result<(usize, usize)>> + '_ {
+        let mut node = 0;
+        let mut peers = Vec::new().into_iter();
+
+        iter::from_fn(move || loop {
+            if let Some(peer) = peers.next() {
+                return Some(Ok((node, peer)));
+            }
+
+            node = self.lists_read;
+            match self.read_peers() {
+                Ok(Some(next)) => peers = next.into_iter(),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        })
+    }
+
+    fn read_str(&mut self) -> io::Result<String> {
+        let id = read_varint(&mut self.reader)?;
+        if id > 0 {
+            return self
+                .strings
+                .get(id as usize - 1)
+                .cloned()
+                .ok_or_else(|| invalid("unknown string reference"));
+        }
+
+        let len = read_varint(&mut self.reader)?;
+        let mut bytes = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let string = String::from_utf8(bytes).map_err(|_| invalid("invalid UTF-8 string"))?;
+        self.strings.push(string.clone());
+
+        Ok(string)
+    }
+
+    fn read_record(&mut self, addr: NodeAddr) -> io::Result<NodeRecord> {
+        let mut record = NodeRecord::new(addr);
+        record.network_type = NetworkType::custom(&self.read_str()?);
+
+        let mut bytes = [0; 2];
+        self.reader.read_exact(&mut bytes)?;
+        let [fields, connection_failures] = bytes;
+        record.connection_failures = connection_failures;
+
+        if fields & RECORD_USER_AGENT != 0 {
+            record.user_agent = Some(self.read_str()?);
+        }
+        if fields & RECORD_PROTOCOL_VERSION != 0 {
+            let version = u32::try_from(read_varint(&mut self.reader)?)
+                .map_err(|_| invalid("protocol version out of range"))?;
+            record.protocol_version = Some(version);
+        }
+        if fields & RECORD_SERVICES != 0 {
+            record.services = Some(read_varint(&mut self.reader)?);
+        }
+        if fields & RECORD_START_HEIGHT != 0 {
+            record.start_height = Some(read_varint(&mut self.reader)?);
+        }
+        if fields & RECORD_HANDSHAKE_LATENCY != 0 {
+            record.handshake_latency = Some(read_duration(&mut self.reader)?);
+        }
+        if fields & RECORD_FIRST_SEEN != 0 {
+            record.first_seen = Some(read_time(&mut self.reader)?);
+        }
+        if fields & RECORD_LAST_SEEN != 0 {
+            record.last_seen = Some(read_time(&mut self.reader)?);
+        }
+
+        Ok(record)
+    }
+}
+
+impl NetworkSummary {
+    /// Writes the summary in the compact binary encoding.
+    ///
+    /// Network types, records and connections, if present, must be given for every node, and
+    /// records must be in the order of `node_addrs`. Nodes of a summary without connections
+    /// are written with empty adjacency lists.
+    pub fn write_binary<W: Write>(&self, writer: W) -> io::Result<()> {
+        let num_nodes = self.node_addrs.len();
+        let aligned = |len: usize| len == num_nodes || len == 0;
+        if !aligned(self.node_network_types.len())
+            || !aligned(self.node_records.len())
+            || !aligned(self.nodes_indices.len())
+        {
+            return Err(misuse("per-node fields don't match node_addrs"));
+        }
+
+        let mut writer = SummaryWriter::new(writer, SummaryHeader::new(self))?;
+        for (index, addr) in self.node_addrs.iter().enumerate() {
+            writer.write_node(&NodeEntry {
+                addr: *addr,
+                network_type: self.node_network_types.get(index).cloned(),
+                record: self.node_records.get(index).cloned(),
+            })?;
+        }
+        for index in 0..num_nodes {
+            writer.write_peers(self.nodes_indices.get(index).map_or(&[], Vec::as_slice))?;
+        }
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Reads a summary written with [`NetworkSummary::write_binary`].
+    pub fn read_binary<R: Read>(reader: R) -> io::Result<Self> {
+        let mut reader = SummaryReader::new(reader)?;
+        let header = reader.header().clone();
+
+        let mut summary = NetworkSummary {
+            num_known_nodes: header.num_known_nodes,
+            num_good_nodes: header.num_good_nodes,
+            num_known_connections: header.num_known_connections,
+            num_versions: header.num_versions,
+            protocol_versions: header.protocol_versions,
+            user_agents: header.user_agents,
+            crawler_runtime: header.crawler_runtime,
+            ..Default::default()
+        };
+        while let Some(node) = reader.read_node()? {
+            summary.node_addrs.push(node.addr);
+            summary.node_network_types.extend(node.network_type);
+            summary.node_records.extend(node.record);
+        }
+        while let Some(peers) = reader.read_peers()? {
+            summary.nodes_indices.push(peers);
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Converts a JSON summary of any known format version into the binary encoding.
+pub fn json_to_binary<R: Read, W: Write>(json: R, binary: W) -> io::Result<()> {
+    let summary = NetworkSummary::from_json_reader(json).map_err(invalid)?;
+    summary.write_binary(binary)
+}
+
+/// Converts a binary encoded summary into JSON.
+pub fn binary_to_json<R: Read, W: Write>(binary: R, json: W) -> io::Result<()> {
+    let summary = NetworkSummary::read_binary(binary)?;
+    serde_json::to_writer(json, &summary)?;
+
+    Ok(())
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn misuse<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    let mut bytes = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes[len] = byte;
+            len += 1;
+            break;
+        }
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+
+    writer.write_all(&bytes[..len])
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        let bits = u64::from(byte[0] & 0x7f);
+        if shift == 63 && bits > 1 {
+            break;
+        }
+
+        value |= bits << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(invalid("varint out of range"))
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> io::Result<usize> {
+    usize::try_from(read_varint(reader)?).map_err(|_| invalid("count out of range"))
+}
+
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn write_duration<W: Write>(writer: &mut W, duration: Duration) -> io::Result<()> {
+    write_varint(writer, duration.as_secs())?;
+    write_varint(writer, duration.subsec_nanos().into())
+}
+
+fn read_duration<R: Read>(reader: &mut R) -> io::Result<Duration> {
+    let secs = read_varint(reader)?;
+    let nanos = read_varint(reader)?;
+    if nanos >= 1_000_000_000 {
+        return Err(invalid("invalid duration"));
+    }
+
+    Ok(Duration::new(secs, nanos as u32))
+}
+
+fn read_time<R: Read>(reader: &mut R) -> io::Result<SystemTime> {
+    UNIX_EPOCH
+        .checked_add(read_duration(reader)?)
+        .ok_or_else(|| invalid("timestamp out of range"))
+}
+
+fn write_addr<W: Write>(writer: &mut W, addr: &NodeAddr) -> io::Result<()> {
+    match addr {
+        NodeAddr::Ip(addr) => match addr.socket_addr().ip() {
+            IpAddr::V4(ip) => {
+                writer.write_all(&[ADDR_IPV4])?;
+                writer.write_all(&ip.octets())?;
+            }
+            IpAddr::V6(ip) => {
+                writer.write_all(&[ADDR_IPV6])?;
+                writer.write_all(&ip.octets())?;
+            }
+        },
+        NodeAddr::TorV3 { pubkey, .. } => {
+            writer.write_all(&[ADDR_TOR_V3])?;
+            writer.write_all(pubkey)?;
+        }
+        NodeAddr::I2p { hash, .. } => {
+            writer.write_all(&[ADDR_I2P])?;
+            writer.write_all(hash)?;
+        }
+        NodeAddr::Cjdns(addr) => {
+            writer.write_all(&[ADDR_CJDNS])?;
+            writer.write_all(&addr.ip().octets())?;
+        }
+    }
+
+    writer.write_all(&addr.port().to_be_bytes())
+}
+
+fn read_addr<R: Read>(reader: &mut R) -> io::Result<NodeAddr> {
+    fn bytes<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Address read before its port.
+    enum Host {
+        Ip(IpAddr),
+        TorV3([u8; 32]),
+        I2p([u8; 32]),
+    }
+
+    let [tag] = bytes(reader)?;
+    let host = match tag {
+        ADDR_IPV4 => Host::Ip(Ipv4Addr::from(bytes::<_, 4>(reader)?).into()),
+        ADDR_IPV6 | ADDR_CJDNS => {
+            let ip = Ipv6Addr::from(bytes::<_, 16>(reader)?);
+            if (tag == ADDR_CJDNS) != (ip.octets()[0] == 0xfc) {
+                return Err(invalid(format!("address of the wrong type: {ip}")));
+            }
+            Host::Ip(ip.into())
+        }
+        ADDR_TOR_V3 => Host::TorV3(bytes(reader)?),
+        ADDR_I2P => Host::I2p(bytes(reader)?),
+        tag => return Err(invalid(format!("unknown address type: {tag}"))),
+    };
+    let port = u16::from_be_bytes(bytes(reader)?);
+
+    Ok(match host {
+        Host::Ip(ip) => NodeAddr::from((ip, port)),
+        Host::TorV3(pubkey) => NodeAddr::TorV3 { pubkey, port },
+        Host::I2p(hash) => NodeAddr::I2p { hash, port },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::addr;
+
+    fn summary() -> NetworkSummary {
+        let onion = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion:443";
+        let mut summary = NetworkSummary {
+            num_known_nodes: 10,
+            num_known_connections: 3,
+            crawler_runtime: Duration::from_millis(90_500),
+            node_addrs: vec![
+                addr(4),
+                "[2001:db8::1]:8233".parse().unwrap(),
+                onion.parse().unwrap(),
+            ],
+            nodes_indices: vec![vec![2, 1], vec![0], vec![]],
+            ..Default::default()
+        };
+        summary.node_records = summary
+            .node_addrs
+            .iter()
+            .map(|addr| NodeRecord::new(*addr))
+            .collect();
+        summary.node_records[0].user_agent = Some("/MagicBean:5.4.2/".to_owned());
+        summary.node_records[0].protocol_version = Some(170100);
+        summary.node_records[0].handshake_latency = Some(Duration::from_micros(1500));
+        summary.node_records[1].user_agent = Some("/MagicBean:5.4.2/".to_owned());
+        summary.node_records[1].network_type = NetworkType::custom("TestNet");
+        summary.node_records[1].last_seen = Some(UNIX_EPOCH + Duration::new(1_700_000_000, 7));
+        summary.node_records[2].connection_failures = 3;
+        summary.derive_from_records();
+
+        summary
+    }
+
+    #[test]
+    fn should_round_trip_through_json() {
+        let json = serde_json::to_vec(&summary()).unwrap();
+
+        let mut binary = Vec::new();
+        json_to_binary(&json[..], &mut binary).unwrap();
+        let mut converted = Vec::new();
+        binary_to_json(&binary[..], &mut converted).unwrap();
+
+        assert!(binary.len() < json.len() / 4);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&converted).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(&json).unwrap()
+        );
+
+        // Summaries without connections convert as well.
+        let mut summary = summary();
+        summary.nodes_indices.clear();
+        let json = serde_json::to_vec(&summary).unwrap();
+        let mut binary = Vec::new();
+        json_to_binary(&json[..], &mut binary).unwrap();
+        let converted = NetworkSummary::read_binary(&binary[..]).unwrap();
+        assert_eq!(converted.nodes_indices, vec![Vec::<usize>::new(); 3]);
+    }
+
+    #[test]
+    fn should_stream_nodes_and_edges() {
+        let summary = summary();
+        let mut binary = Vec::new();
+        summary.write_binary(&mut binary).unwrap();
+
+        let mut reader = SummaryReader::new(&binary[..]).unwrap();
+        assert_eq!(reader.header().num_nodes, 3);
+        assert!(reader.read_peers().is_err());
+
+        let nodes: Vec<NodeEntry> = reader.nodes().collect::<io::Result<_>>().unwrap();
+        assert_eq!(nodes[2].addr, summary.node_addrs[2]);
+        assert_eq!(nodes[1].record.as_ref(), summary.node_records.get(1));
+        assert_eq!(nodes[1].network_type.as_ref().unwrap().name(), "TestNet");
+
+        let edges: Vec<(usize, usize)> = reader.edges().collect::<io::Result<_>>().unwrap();
+        assert_eq!(edges, vec![(0, 2), (0, 1), (1, 0)]);
+
+        binary[4] = FORMAT_VERSION + 1;
+        assert!(SummaryReader::new(&binary[..]).is_err());
+
+        // Peers must be indices of nodes.
+        let header = SummaryHeader {
+            num_nodes: 1,
+            ..Default::default()
+        };
+        let mut writer = SummaryWriter::new(Vec::new(), header).unwrap();
+        writer
+            .write_node(&NodeEntry {
+                addr: summary.node_addrs[0],
+                network_type: None,
+                record: None,
+            })
+            .unwrap();
+        assert!(writer.write_peers(&[1]).is_err());
+        assert!(writer.write_peers(&[]).is_ok());
+    }
+}
@@ -0,0 +1,350 @@
+//! Community detection over the crawled network topology.
+use std::collections::{BTreeMap, HashMap};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export::NodeAttributes,
+    graph::{adjacency_sets, sorted_neighbours},
+    summary::{NetworkSummary, NodesIndices},
+};
+
+/// Maximum number of label propagation rounds.
+const LABEL_PROPAGATION_MAX_ROUNDS: usize = 100;
+/// Name of the attribute used when exporting communities.
+const COMMUNITY_ATTRIBUTE: &str = "community";
+
+/// Supported community detection algorithms.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CommunityAlgorithm {
+    /// Louvain modularity optimisation.
+    Louvain,
+    /// Asynchronous label propagation, node visiting order is determined by `seed`.
+    LabelPropagation { seed: u64 },
+}
+
+/// Partition of the network into communities.
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct Communities {
+    /// Community id of each node. Indexes correspond to [`NetworkSummary::node_addrs`].
+    pub membership: Vec<usize>,
+    /// Modularity of the partition.
+    pub modularity: f64,
+}
+
+impl Communities {
+    /// Detects communities in the given adjacency list.
+    pub fn new(indices: &NodesIndices, algorithm: CommunityAlgorithm) -> Self {
+        let neighbours = sorted_neighbours(&adjacency_sets(indices));
+
+        let membership = match algorithm {
+            CommunityAlgorithm::Louvain => louvain(&neighbours),
+            CommunityAlgorithm::LabelPropagation { seed } => label_propagation(&neighbours, seed),
+        };
+        let membership = normalize(&membership);
+
+        Self {
+            modularity: modularity(&neighbours, &membership),
+            membership,
+        }
+    }
+
+    /// Returns the number of detected communities.
+    pub fn num_communities(&self) -> usize {
+        self.membership
+            .iter()
+            .max()
+            .map(|id| id + 1)
+            .unwrap_or_default()
+    }
+
+    /// Returns the members of each community.
+    pub fn members(&self) -> Vec<Vec<usize>> {
+        let mut members = vec![Vec::new(); self.num_communities()];
+        for (node, community) in self.membership.iter().enumerate() {
+            members[*community].push(node);
+        }
+        members
+    }
+
+    /// Returns community ids as an attribute which can be passed to graph exporters.
+    pub fn to_attributes(&self) -> NodeAttributes {
+        NodeAttributes::from([(
+            COMMUNITY_ATTRIBUTE.to_owned(),
+            self.membership.iter().map(|id| id.to_string()).collect(),
+        )])
+    }
+}
+
+impl NetworkSummary {
+    /// Detects communities in the crawled network.
+    pub fn communities(&self, algorithm: CommunityAlgorithm) -> Communities {
+        Communities::new(&self.nodes_indices, algorithm)
+    }
+}
+
+/// Renumbers communities to consecutive ids in order of the first node of each community.
+fn normalize(membership: &[usize]) -> Vec<usize> {
+    let mut ids = HashMap::new();
+    membership
+        .iter()
+        .map(|community| {
+            let next = ids.len();
+            *ids.entry(*community).or_insert(next)
+        })
+        .collect()
+}
+
+/// Computes the modularity of a partition of an unweighted graph.
+fn modularity(neighbours: &[Vec<usize>], membership: &[usize]) -> f64 {
+    let degree_sum: usize = neighbours.iter().map(Vec::len).sum();
+    if degree_sum == 0 {
+        return 0.0;
+    }
+
+    let num_communities = membership.iter().max().map(|id| id + 1).unwrap_or_default();
+    let mut internal = vec![0.0; num_communities];
+    let mut totals = vec![0.0; num_communities];
+
+    for (node, list) in neighbours.iter().enumerate() {
+        totals[membership[node]] += list.len() as f64;
+        for neighbour in list {
+            if membership[*neighbour] == membership[node] {
+                internal[membership[node]] += 1.0;
+            }
+        }
+    }
+
+    let m2 = degree_sum as f64;
+    internal
+        .iter()
+        .zip(&totals)
+        .map(|(inside, total)| inside / m2 - (total / m2).powi(2))
+        .sum()
+}
+
+/// Weighted graph used by the Louvain algorithm.
+struct WeightedGraph {
+    /// Weighted connections of each node, without self loops.
+    edges: Vec<Vec<(usize, f64)>>,
+    /// Weight of each node's self loop.
+    loops: Vec<f64>,
+}
+
+impl WeightedGraph {
+    fn degree(&self, node: usize) -> f64 {
+        self.edges[node]
+            .iter()
+            .map(|(_, weight)| weight)
+            .sum::<f64>()
+            + 2.0 * self.loops[node]
+    }
+}
+
+/// Runs the Louvain algorithm and returns the community of each node.
+fn louvain(neighbours: &[Vec<usize>]) -> Vec<usize> {
+    let mut graph = WeightedGraph {
+        edges: neighbours
+            .iter()
+            .map(|list| list.iter().map(|neighbour| (*neighbour, 1.0)).collect())
+            .collect(),
+        loops: vec![0.0; neighbours.len()],
+    };
+    let mut membership: Vec<usize> = (0..neighbours.len()).collect();
+
+    loop {
+        let (communities, moved) = louvain_local_moving(&graph);
+        if !moved {
+            break;
+        }
+
+        let communities = normalize(&communities);
+        membership
+            .iter_mut()
+            .for_each(|community| *community = communities[*community]);
+        graph = aggregate(&graph, &communities);
+    }
+
+    membership
+}
+
+/// Moves nodes between neighbouring communities while modularity improves.
+///
+/// Returns the community of each node and whether any node has moved.
+fn louvain_local_moving(graph: &WeightedGraph) -> (Vec<usize>, bool) {
+    let n = graph.edges.len();
+    let degrees: Vec<f64> = (0..n).map(|node| graph.degree(node)).collect();
+    let m2: f64 = degrees.iter().sum();
+    let mut communities: Vec<usize> = (0..n).collect();
+    let mut totals = degrees.clone();
+    let mut moved = false;
+
+    if m2 == 0.0 {
+        return (communities, false);
+    }
+
+    loop {
+        let mut improved = false;
+
+        for node in 0..n {
+            let current = communities[node];
+            totals[current] -= degrees[node];
+
+            // BTreeMap keeps the choice of the best community deterministic.
+            let mut links: BTreeMap<usize, f64> = BTreeMap::from([(current, 0.0)]);
+            for (neighbour, weight) in &graph.edges[node] {
+                *links.entry(communities[*neighbour]).or_default() += weight;
+            }
+
+            let gain =
+                |community: usize, weight: f64| weight - totals[community] * degrees[node] / m2;
+            let mut best = current;
+            let mut best_gain = gain(current, links[&current]);
+            for (community, weight) in &links {
+                let candidate = gain(*community, *weight);
+                if candidate > best_gain + f64::EPSILON {
+                    best = *community;
+                    best_gain = candidate;
+                }
+            }
+
+            totals[best] += degrees[node];
+            if best != current {
+                communities[node] = best;
+                improved = true;
+                moved = true;
+            }
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    (communities, moved)
+}
+
+/// Builds a graph whose nodes are the communities of the given graph.
+fn aggregate(graph: &WeightedGraph, communities: &[usize]) -> WeightedGraph {
+    let n = communities
+        .iter()
+        .max()
+        .map(|id| id + 1)
+        .unwrap_or_default();
+    let mut edges: Vec<BTreeMap<usize, f64>> = vec![BTreeMap::new(); n];
+    let mut loops = vec![0.0; n];
+
+    for (node, list) in graph.edges.iter().enumerate() {
+        let community = communities[node];
+        loops[community] += graph.loops[node];
+
+        for (neighbour, weight) in list {
+            let other = communities[*neighbour];
+            if other == community {
+                // Every connection is listed from both of its sides.
+                loops[community] += weight / 2.0;
+            } else {
+                *edges[community].entry(other).or_default() += weight;
+            }
+        }
+    }
+
+    WeightedGraph {
+        edges: edges
+            .into_iter()
+            .map(|map| map.into_iter().collect())
+            .collect(),
+        loops,
+    }
+}
+
+/// Runs asynchronous label propagation and returns the label of each node.
+fn label_propagation(neighbours: &[Vec<usize>], seed: u64) -> Vec<usize> {
+    let n = neighbours.len();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut labels: Vec<usize> = (0..n).collect();
+    let mut order: Vec<usize> = (0..n).collect();
+
+    for _ in 0..LABEL_PROPAGATION_MAX_ROUNDS {
+        order.shuffle(&mut rng);
+        let mut changed = false;
+
+        for &node in &order {
+            if neighbours[node].is_empty() {
+                continue;
+            }
+
+            let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+            for neighbour in &neighbours[node] {
+                *counts.entry(labels[*neighbour]).or_default() += 1;
+            }
+
+            let max = counts.values().copied().max().unwrap_or_default();
+            // Keeping the current label on ties guarantees convergence.
+            if counts.get(&labels[node]) == Some(&max) {
+                continue;
+            }
+
+            let candidates: Vec<usize> = counts
+                .into_iter()
+                .filter(|(_, count)| *count == max)
+                .map(|(label, _)| label)
+                .collect();
+            if let Some(label) = candidates.choose(&mut rng) {
+                labels[node] = *label;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    labels
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Two 4-node cliques joined by a single connection between nodes 3 and 4.
+    fn two_cliques() -> NodesIndices {
+        let mut indices = vec![Vec::new(); 8];
+        for group in [0..4, 4..8] {
+            for a in group.clone() {
+                for b in group.clone() {
+                    if a != b {
+                        indices[a].push(b);
+                    }
+                }
+            }
+        }
+        indices[3].push(4);
+        indices[4].push(3);
+        indices
+    }
+
+    #[test]
+    fn should_detect_cliques_with_louvain() {
+        let communities = Communities::new(&two_cliques(), CommunityAlgorithm::Louvain);
+
+        assert_eq!(communities.membership, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(communities.num_communities(), 2);
+        // 2 * (12 / 26 - (13 / 26)^2)
+        assert!((communities.modularity - 0.423_076_923).abs() < 1e-6);
+    }
+
+    #[test]
+    fn should_detect_cliques_with_label_propagation() {
+        let algorithm = CommunityAlgorithm::LabelPropagation { seed: 1 };
+        let communities = Communities::new(&two_cliques(), algorithm);
+
+        assert_eq!(communities.membership, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(
+            communities.to_attributes()[COMMUNITY_ATTRIBUTE],
+            vec!["0", "0", "0", "0", "1", "1", "1", "1"]
+        );
+    }
+}
@@ -1,19 +1,26 @@
 use std::{
     cmp::Ordering,
     hash::{Hash, Hasher},
-    net::SocketAddr,
-    time::Instant,
+    time::SystemTime,
 };
 
+use serde::{Deserialize, Serialize};
+
+use crate::address::NodeAddr;
+
 /// A connection found in the network.
-#[derive(Debug, Eq, Copy, Clone)]
+#[derive(Debug, Eq, Copy, Clone, Deserialize, Serialize)]
 pub struct KnownConnection {
     /// One of the two sides of a connection.
-    pub a: SocketAddr,
+    pub a: NodeAddr,
     /// The other side of a connection.
-    pub b: SocketAddr,
+    pub b: NodeAddr,
+    /// The timestamp of the first time the connection was seen.
+    ///
+    /// Wall-clock time is used so that connections keep their history across restarts.
+    pub first_seen: SystemTime,
     /// The timestamp of the last time the connection was seen.
-    pub last_seen: Instant,
+    pub last_seen: SystemTime,
 }
 
 impl Hash for KnownConnection {
@@ -35,11 +42,14 @@ impl Hash for KnownConnection {
 }
 
 impl KnownConnection {
-    pub fn new(a: SocketAddr, b: SocketAddr) -> Self {
+    pub fn new(a: NodeAddr, b: NodeAddr) -> Self {
+        let now = SystemTime::now();
+
         Self {
             a,
             b,
-            last_seen: Instant::now(),
+            first_seen: now,
+            last_seen: now,
         }
     }
 }
@@ -55,14 +65,29 @@ impl PartialEq for KnownConnection {
 
 #[cfg(test)]
 mod test {
-    use std::{collections::HashSet, net::SocketAddr, str::FromStr};
+    use std::{collections::HashSet, str::FromStr};
 
-    use crate::connection::KnownConnection;
+    use crate::{address::NodeAddr, connection::KnownConnection};
 
     #[test]
     fn should_deal_with_reverse_connection() {
-        let a = SocketAddr::from_str("1.2.3.4:3000").unwrap();
-        let b = SocketAddr::from_str("1.2.3.5:3000").unwrap();
+        let a = NodeAddr::from_str("1.2.3.4:3000").unwrap();
+        let b = NodeAddr::from_str("1.2.3.5:3000").unwrap();
+        let connection_present = KnownConnection::new(a, b);
+        let connection_reverse = KnownConnection::new(b, a);
+        assert_eq!(connection_present, connection_reverse);
+        let mut set = HashSet::new();
+        set.insert(connection_present);
+        assert!(set.contains(&connection_reverse));
+    }
+
+    #[test]
+    fn should_deal_with_reverse_tor_connection() {
+        let a = NodeAddr::from_str("1.2.3.4:3000").unwrap();
+        let b = NodeAddr::from_str(
+            "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion:3000",
+        )
+        .unwrap();
         let connection_present = KnownConnection::new(a, b);
         let connection_reverse = KnownConnection::new(b, a);
         assert_eq!(connection_present, connection_reverse);
@@ -0,0 +1,379 @@
+//! IP diversity analysis of crawled nodes, used to spot node farms and Sybil candidates.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{address::NodeAddr, summary::NetworkSummary};
+
+/// An IP network prefix, e.g. `1.2.0.0/16`.
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct IpPrefix {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpPrefix {
+    /// Creates the prefix of the given length containing the address.
+    ///
+    /// The length is clamped to the address size. IPv4-mapped IPv6 addresses are treated as IPv4.
+    pub fn new(ip: IpAddr, prefix_len: u8) -> Self {
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => {
+                let prefix_len = prefix_len.min(32);
+                let mask = u32::MAX
+                    .checked_shl(32 - prefix_len as u32)
+                    .unwrap_or_default();
+                Self {
+                    addr: Ipv4Addr::from(u32::from(ip) & mask).into(),
+                    prefix_len,
+                }
+            }
+            IpAddr::V6(ip) => {
+                let prefix_len = prefix_len.min(128);
+                let mask = u128::MAX
+                    .checked_shl(128 - prefix_len as u32)
+                    .unwrap_or_default();
+                Self {
+                    addr: Ipv6Addr::from(u128::from(ip) & mask).into(),
+                    prefix_len,
+                }
+            }
+        }
+    }
+
+    /// Returns the first address of the prefix.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns the prefix length in bits.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns true if the prefix contains the address.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        ip.is_ipv4() == self.addr.is_ipv4() && IpPrefix::new(ip, self.prefix_len) == *self
+    }
+}
+
+impl fmt::Display for IpPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for IpPrefix {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = input
+            .split_once('/')
+            .ok_or_else(|| format!("missing prefix length: {input}"))?;
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid address: {addr}"))?;
+        let prefix_len: u8 = prefix_len
+            .parse()
+            .map_err(|_| format!("invalid length: {prefix_len}"))?;
+
+        Ok(IpPrefix::new(addr, prefix_len))
+    }
+}
+
+impl Serialize for IpPrefix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpPrefix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Thresholds used to flag suspicious concentrations of nodes.
+#[derive(Debug, Clone)]
+pub struct DiversityConfig {
+    /// Prefixes holding a larger share of all nodes of their address family are flagged.
+    pub max_prefix_share: f64,
+    /// Prefixes holding fewer nodes are never flagged.
+    pub min_flagged_nodes: usize,
+    /// IP addresses hosting more nodes (on different ports) are flagged.
+    pub max_nodes_per_ip: usize,
+}
+
+impl Default for DiversityConfig {
+    fn default() -> Self {
+        Self {
+            max_prefix_share: 0.05,
+            min_flagged_nodes: 3,
+            max_nodes_per_ip: 1,
+        }
+    }
+}
+
+/// Distribution of nodes over prefixes of a single length.
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct PrefixDistribution {
+    /// Prefix length in bits.
+    pub prefix_len: u8,
+    /// Number of nodes of the matching address family.
+    pub num_nodes: usize,
+    /// Map: Prefix -> number of nodes within it.
+    pub groups: BTreeMap<IpPrefix, usize>,
+    /// Shannon entropy of the distribution in bits.
+    pub entropy: f64,
+    /// Entropy divided by its maximum, `1.0` means that every node is in a different prefix.
+    pub diversity_score: f64,
+}
+
+impl PrefixDistribution {
+    fn new<'a, I: Iterator<Item = &'a IpAddr>>(ips: I, prefix_len: u8) -> Self {
+        let mut groups = BTreeMap::new();
+        for ip in ips {
+            *groups.entry(IpPrefix::new(*ip, prefix_len)).or_default() += 1;
+        }
+
+        let num_nodes = groups.values().sum();
+        let entropy = entropy(groups.values().copied(), num_nodes);
+        let diversity_score = if num_nodes > 1 {
+            entropy / (num_nodes as f64).log2()
+        } else {
+            1.0
+        };
+
+        Self {
+            prefix_len,
+            num_nodes,
+            groups,
+            entropy,
+            diversity_score,
+        }
+    }
+}
+
+/// A prefix holding a suspicious share of nodes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SuspiciousPrefix {
+    /// The flagged prefix.
+    pub prefix: IpPrefix,
+    /// Number of nodes within the prefix.
+    pub num_nodes: usize,
+    /// Share of all nodes of the same address family.
+    pub share: f64,
+}
+
+/// IP diversity of crawled nodes.
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct DiversityReport {
+    /// Nodes grouped by IPv4 /16 prefixes.
+    pub ipv4_16: PrefixDistribution,
+    /// Nodes grouped by IPv4 /24 prefixes.
+    pub ipv4_24: PrefixDistribution,
+    /// Nodes grouped by IPv6 /32 prefixes.
+    pub ipv6_32: PrefixDistribution,
+    /// Nodes grouped by IPv6 /48 prefixes.
+    pub ipv6_48: PrefixDistribution,
+    /// Map: IP address -> ports of all nodes sharing it, for addresses hosting too many nodes.
+    pub shared_ips: BTreeMap<IpAddr, Vec<u16>>,
+    /// Prefixes holding a suspicious share of nodes, sorted from the largest share.
+    ///
+    /// A prefix is omitted if all of its nodes are within a longer flagged prefix, so that
+    /// a single farm is only reported once.
+    pub suspicious_prefixes: Vec<SuspiciousPrefix>,
+    /// Diversity score over the IPv4 /24 and IPv6 /48 prefixes of all nodes, from 0 to 1.
+    pub diversity_score: f64,
+}
+
+impl DiversityReport {
+    /// Analyses the given node addresses, overlay network addresses are skipped.
+    pub fn new(addrs: &[NodeAddr], config: &DiversityConfig) -> Self {
+        let addrs: Vec<SocketAddr> = addrs.iter().filter_map(NodeAddr::socket_addr).collect();
+        let ips: Vec<IpAddr> = addrs.iter().map(|addr| addr.ip().to_canonical()).collect();
+        let ipv4 = || ips.iter().filter(|ip| ip.is_ipv4());
+        let ipv6 = || ips.iter().filter(|ip| ip.is_ipv6());
+
+        let mut ports: HashMap<IpAddr, Vec<u16>> = HashMap::new();
+        for (ip, addr) in ips.iter().zip(&addrs) {
+            ports.entry(*ip).or_default().push(addr.port());
+        }
+        let shared_ips = ports
+            .into_iter()
+            .filter(|(_, ports)| ports.len() > config.max_nodes_per_ip)
+            .map(|(ip, mut ports)| {
+                ports.sort_unstable();
+                (ip, ports)
+            })
+            .collect();
+
+        let mut report = Self {
+            ipv4_16: PrefixDistribution::new(ipv4(), 16),
+            ipv4_24: PrefixDistribution::new(ipv4(), 24),
+            ipv6_32: PrefixDistribution::new(ipv6(), 32),
+            ipv6_48: PrefixDistribution::new(ipv6(), 48),
+            shared_ips,
+            ..Default::default()
+        };
+
+        let subnets: Vec<usize> = report
+            .ipv4_24
+            .groups
+            .values()
+            .chain(report.ipv6_48.groups.values())
+            .copied()
+            .collect();
+        report.diversity_score = if ips.len() > 1 {
+            entropy(subnets.into_iter(), ips.len()) / (ips.len() as f64).log2()
+        } else {
+            1.0
+        };
+
+        // Longer prefixes first, so that shorter ones covering the same nodes can be skipped.
+        for distribution in [
+            &report.ipv4_24,
+            &report.ipv4_16,
+            &report.ipv6_48,
+            &report.ipv6_32,
+        ] {
+            for (prefix, count) in &distribution.groups {
+                let share = *count as f64 / distribution.num_nodes as f64;
+                let covered = report.suspicious_prefixes.iter().any(|suspicious| {
+                    suspicious.num_nodes == *count && prefix.contains(suspicious.prefix.addr())
+                });
+                if *count >= config.min_flagged_nodes && share > config.max_prefix_share && !covered
+                {
+                    report.suspicious_prefixes.push(SuspiciousPrefix {
+                        prefix: *prefix,
+                        num_nodes: *count,
+                        share,
+                    });
+                }
+            }
+        }
+        report
+            .suspicious_prefixes
+            .sort_by(|a, b| b.share.total_cmp(&a.share));
+
+        report
+    }
+}
+
+impl NetworkSummary {
+    /// Analyses IP diversity of the good nodes.
+    pub fn ip_diversity(&self, config: &DiversityConfig) -> DiversityReport {
+        DiversityReport::new(&self.node_addrs, config)
+    }
+}
+
+/// Shannon entropy, in bits, of a distribution given by its counts.
+pub(crate) fn entropy<I: Iterator<Item = usize>>(counts: I, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    counts
+        .filter(|count| *count > 0)
+        .map(|count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+impl fmt::Display for DiversityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "IP diversity:\n")?;
+        writeln!(f, "Diversity score: {:.3}", self.diversity_score)?;
+        for distribution in [&self.ipv4_16, &self.ipv4_24, &self.ipv6_32, &self.ipv6_48] {
+            writeln!(
+                f,
+                "/{}: {} node(s) in {} prefix(es), entropy {:.3} bits, score {:.3}",
+                distribution.prefix_len,
+                distribution.num_nodes,
+                distribution.groups.len(),
+                distribution.entropy,
+                distribution.diversity_score
+            )?;
+        }
+
+        writeln!(f, "\nSuspicious prefixes:")?;
+        for suspicious in &self.suspicious_prefixes {
+            writeln!(
+                f,
+                "{}: {} node(s) ({:.1}%)",
+                suspicious.prefix,
+                suspicious.num_nodes,
+                suspicious.share * 100.0
+            )?;
+        }
+
+        writeln!(f, "\nShared IP addresses:")?;
+        for (ip, ports) in &self.shared_ips {
+            writeln!(f, "{ip}: {} node(s)", ports.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_mask_prefixes() {
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        assert_eq!(IpPrefix::new(ip, 16).to_string(), "1.2.0.0/16");
+        assert_eq!(
+            "1.2.3.4/24".parse::<IpPrefix>().unwrap().to_string(),
+            "1.2.3.0/24"
+        );
+
+        let ip: IpAddr = "2001:db8:abcd:12::1".parse().unwrap();
+        assert_eq!(IpPrefix::new(ip, 48).to_string(), "2001:db8:abcd::/48");
+        assert!(IpPrefix::new(ip, 32).contains("2001:db8::1".parse().unwrap()));
+
+        let mapped: IpAddr = "::ffff:1.2.3.4".parse().unwrap();
+        assert_eq!(IpPrefix::new(mapped, 16).to_string(), "1.2.0.0/16");
+    }
+
+    #[test]
+    fn should_flag_node_farms() {
+        let mut addrs: Vec<NodeAddr> = (0..10)
+            .map(|i| NodeAddr::from(([10, i, 0, 1], 8233)))
+            .collect();
+        // A farm of four nodes on a single IP address.
+        addrs.extend((0..4).map(|port| NodeAddr::from(([5, 6, 7, 8], 9000 + port))));
+
+        let config = DiversityConfig {
+            max_prefix_share: 0.2,
+            ..Default::default()
+        };
+        let report = DiversityReport::new(&addrs, &config);
+
+        assert_eq!(report.ipv4_16.groups.len(), 11);
+        assert_eq!(report.shared_ips.len(), 1);
+        assert_eq!(
+            report.shared_ips[&"5.6.7.8".parse::<IpAddr>().unwrap()],
+            vec![9000, 9001, 9002, 9003]
+        );
+        // The farm is within both a flagged /16 and /24, but only reported once.
+        assert_eq!(report.suspicious_prefixes.len(), 1);
+        assert_eq!(
+            report.suspicious_prefixes[0].prefix.to_string(),
+            "5.6.7.0/24"
+        );
+        assert_eq!(report.suspicious_prefixes[0].num_nodes, 4);
+        assert!(report.diversity_score < 1.0 && report.diversity_score > 0.5);
+    }
+}
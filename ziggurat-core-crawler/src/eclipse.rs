@@ -0,0 +1,351 @@
+//! Eclipse-attack susceptibility of crawled nodes.
+use std::{cmp, collections::HashMap, fmt, hash::Hash};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address::NodeAddr,
+    diversity::{entropy, IpPrefix},
+    graph::{adjacency_sets, sorted_neighbours},
+    summary::{NetworkSummary, NodesIndices},
+};
+
+/// Prefix lengths used to measure the diversity of a neighbourhood, as (IPv4, IPv6).
+const PREFIX_LENS: (u8, u8) = (16, 32);
+/// Prefix lengths of subnets likely run by the same operator, as (IPv4, IPv6).
+const SUBNET_LENS: (u8, u8) = (24, 48);
+
+/// Parameters of the susceptibility scoring.
+#[derive(Debug, Clone)]
+pub struct EclipseConfig {
+    /// Nodes with at least this many connections get no penalty for their degree.
+    pub safe_degree: usize,
+    /// Number of nodes controlled by the attacker.
+    pub attacker_nodes: usize,
+}
+
+impl Default for EclipseConfig {
+    fn default() -> Self {
+        Self {
+            safe_degree: 8,
+            attacker_nodes: 8,
+        }
+    }
+}
+
+/// Individual susceptibility factors of a node, each from 0 (safe) to 1 (exposed).
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct ExposureFactors {
+    /// Penalty for having fewer than the safe number of connections.
+    pub degree: f64,
+    /// Lack of prefix diversity among the neighbours.
+    pub prefix_concentration: f64,
+    /// Share of the neighbours within the largest common subnet.
+    pub subnet_share: f64,
+    /// Share of the neighbours within the largest common ASN, `None` if ASNs are unknown.
+    pub asn_share: Option<f64>,
+    /// Share of the neighbours controlled by the attacker.
+    pub attacker_coverage: f64,
+}
+
+impl ExposureFactors {
+    /// Returns the mean of the known factors.
+    pub fn score(&self) -> f64 {
+        let factors = [
+            Some(self.degree),
+            Some(self.prefix_concentration),
+            Some(self.subnet_share),
+            self.asn_share,
+            Some(self.attacker_coverage),
+        ];
+        let known: Vec<f64> = factors.into_iter().flatten().collect();
+
+        known.iter().sum::<f64>() / known.len() as f64
+    }
+}
+
+/// Susceptibility of a single node.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NodeExposure {
+    /// Index of the node in `node_addrs`.
+    pub node: usize,
+    /// Address of the node.
+    pub addr: NodeAddr,
+    /// Overall score from 0 (safe) to 1 (exposed).
+    pub score: f64,
+    /// Number of connections of the node.
+    pub degree: usize,
+    /// Normalized entropy of the neighbours' prefixes, 1 means every neighbour is in a
+    /// different prefix.
+    pub prefix_diversity: f64,
+    /// Number of neighbours within the largest common subnet.
+    pub max_same_subnet: usize,
+    /// Number of neighbours within the largest common ASN, `None` if ASNs are unknown.
+    pub max_same_asn: Option<usize>,
+    /// Number of neighbours controlled by the attacker.
+    pub attacker_neighbours: usize,
+    /// Breakdown of the score.
+    pub factors: ExposureFactors,
+}
+
+/// Nodes ranked by how easily they could be eclipsed.
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct EclipseReport {
+    /// Nodes the attacker is assumed to control, in the order they were chosen.
+    pub attacker_nodes: Vec<usize>,
+    /// All nodes, sorted from the most exposed.
+    pub ranking: Vec<NodeExposure>,
+}
+
+impl EclipseReport {
+    /// Scores every node of the graph.
+    ///
+    /// `asns` holds the autonomous system number of each node and may be empty if unknown.
+    /// The attacker is assumed to control the nodes covering the most neighbourhoods, chosen
+    /// greedily: each one is the node neighbouring the most nodes not yet neighbouring an
+    /// attacker. Overlay network neighbours can't be located, so they are counted as a single
+    /// prefix. Nodes without an address are only taken into account as neighbours.
+    pub fn new(
+        addrs: &[NodeAddr],
+        indices: &NodesIndices,
+        asns: &[Option<u32>],
+        config: &EclipseConfig,
+    ) -> Self {
+        let neighbours = sorted_neighbours(&adjacency_sets(indices));
+
+        let attacker_nodes = greedy_coverage(&neighbours, config.attacker_nodes);
+        let mut is_attacker = vec![false; neighbours.len()];
+        for node in &attacker_nodes {
+            is_attacker[*node] = true;
+        }
+
+        let prefix = |node: usize, (v4, v6): (u8, u8)| {
+            addrs
+                .get(node)
+                .and_then(NodeAddr::ip)
+                .map(|ip| IpPrefix::new(ip, if ip.to_canonical().is_ipv4() { v4 } else { v6 }))
+        };
+
+        let mut ranking: Vec<NodeExposure> = neighbours
+            .iter()
+            .zip(addrs)
+            .enumerate()
+            .map(|(node, (peers, addr))| {
+                let degree = peers.len();
+                let prefix_diversity = match degree {
+                    0 | 1 => 0.0,
+                    _ => {
+                        let groups =
+                            group_sizes(peers.iter().map(|peer| prefix(*peer, PREFIX_LENS)));
+                        entropy(groups.into_iter(), degree) / (degree as f64).log2()
+                    }
+                };
+                let max_same_subnet =
+                    largest_group(peers.iter().filter_map(|peer| prefix(*peer, SUBNET_LENS)));
+                let max_same_asn = (!asns.is_empty()).then(|| {
+                    largest_group(
+                        peers
+                            .iter()
+                            .filter_map(|peer| asns.get(*peer).copied().flatten()),
+                    )
+                });
+                let attacker_neighbours = peers.iter().filter(|peer| is_attacker[**peer]).count();
+
+                let share = |count: usize| match degree {
+                    0 => 1.0,
+                    _ => count as f64 / degree as f64,
+                };
+                let factors = ExposureFactors {
+                    degree: match config.safe_degree {
+                        0 => 0.0,
+                        safe => 1.0 - degree.min(safe) as f64 / safe as f64,
+                    },
+                    prefix_concentration: 1.0 - prefix_diversity,
+                    subnet_share: share(max_same_subnet),
+                    asn_share: max_same_asn.map(share),
+                    attacker_coverage: share(attacker_neighbours),
+                };
+
+                NodeExposure {
+                    node,
+                    addr: *addr,
+                    score: factors.score(),
+                    degree,
+                    prefix_diversity,
+                    max_same_subnet,
+                    max_same_asn,
+                    attacker_neighbours,
+                    factors,
+                }
+            })
+            .collect();
+        ranking.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.node.cmp(&b.node)));
+
+        Self {
+            attacker_nodes,
+            ranking,
+        }
+    }
+}
+
+impl NetworkSummary {
+    /// Ranks the good nodes by their eclipse-attack susceptibility.
+    ///
+    /// `asns` holds the autonomous system number of each node and may be empty if unknown.
+    pub fn eclipse_susceptibility(
+        &self,
+        asns: &[Option<u32>],
+        config: &EclipseConfig,
+    ) -> EclipseReport {
+        EclipseReport::new(&self.node_addrs, &self.nodes_indices, asns, config)
+    }
+}
+
+/// Picks up to `count` nodes one by one, each time the node neighbouring the most nodes which
+/// don't neighbour an already picked one yet, the lowest index on ties.
+fn greedy_coverage(neighbours: &[Vec<usize>], count: usize) -> Vec<usize> {
+    let mut picked = Vec::new();
+    let mut is_picked = vec![false; neighbours.len()];
+    let mut covered = vec![false; neighbours.len()];
+
+    while picked.len() < count.min(neighbours.len()) {
+        let gain = |node: usize| {
+            neighbours[node]
+                .iter()
+                .filter(|peer| !covered[**peer])
+                .count()
+        };
+        let Some(best) = (0..neighbours.len())
+            .filter(|node| !is_picked[*node])
+            .max_by_key(|node| (gain(*node), cmp::Reverse(*node)))
+        else {
+            break;
+        };
+
+        picked.push(best);
+        is_picked[best] = true;
+        for peer in &neighbours[best] {
+            covered[*peer] = true;
+        }
+    }
+
+    picked
+}
+
+/// Returns the sizes of the groups of equal keys.
+fn group_sizes<K: Eq + Hash, I: Iterator<Item = K>>(keys: I) -> Vec<usize> {
+    let mut groups: HashMap<K, usize> = HashMap::new();
+    for key in keys {
+        *groups.entry(key).or_default() += 1;
+    }
+
+    groups.into_values().collect()
+}
+
+/// Returns the size of the largest group of equal keys.
+fn largest_group<K: Eq + Hash, I: Iterator<Item = K>>(keys: I) -> usize {
+    group_sizes(keys).into_iter().max().unwrap_or_default()
+}
+
+impl fmt::Display for EclipseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Eclipse-attack susceptibility:\n")?;
+        writeln!(f, "Attacker controls {} node(s)", self.attacker_nodes.len())?;
+
+        for exposure in &self.ranking {
+            write!(
+                f,
+                "{}: score {:.3}, degree {}, prefix diversity {:.3}, same subnet {}",
+                exposure.addr,
+                exposure.score,
+                exposure.degree,
+                exposure.prefix_diversity,
+                exposure.max_same_subnet
+            )?;
+            if let Some(max_same_asn) = exposure.max_same_asn {
+                write!(f, ", same ASN {max_same_asn}")?;
+            }
+            writeln!(f, ", attacker neighbours {}", exposure.attacker_neighbours)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::addr;
+
+    #[test]
+    fn should_rank_exposed_nodes_first() {
+        // Hub 0 with three leaves in a single subnet and a short chain 0 - 4 - 5.
+        let addrs: Vec<NodeAddr> = [
+            [1, 1, 0, 1],
+            [10, 0, 0, 1],
+            [10, 0, 0, 2],
+            [10, 0, 0, 3],
+            [20, 0, 0, 1],
+            [30, 0, 0, 1],
+        ]
+        .into_iter()
+        .map(|ip| NodeAddr::from((ip, 8233)))
+        .collect();
+        let indices = vec![vec![1, 2, 3, 4], vec![], vec![], vec![], vec![5], vec![]];
+        let asns = vec![Some(1), Some(2), Some(2), Some(2), Some(3), None];
+        let config = EclipseConfig {
+            attacker_nodes: 1,
+            ..Default::default()
+        };
+
+        let report = EclipseReport::new(&addrs, &indices, &asns, &config);
+
+        assert_eq!(report.attacker_nodes, vec![0]);
+        // Leaves connected only to the attacker are the most exposed.
+        assert_eq!(report.ranking[0].node, 1);
+        assert_eq!(report.ranking[0].factors.attacker_coverage, 1.0);
+        assert_eq!(report.ranking.last().unwrap().node, 4);
+
+        let hub = report.ranking.iter().find(|e| e.node == 0).unwrap();
+        assert_eq!(hub.degree, 4);
+        assert_eq!(hub.max_same_subnet, 3);
+        assert_eq!(hub.max_same_asn, Some(3));
+        assert_eq!(hub.attacker_neighbours, 0);
+
+        let without_asns = EclipseReport::new(&addrs, &indices, &[], &config);
+        assert!(without_asns
+            .ranking
+            .iter()
+            .all(|e| e.max_same_asn.is_none()));
+    }
+
+    #[test]
+    fn should_pick_attackers_covering_most_nodes() {
+        // Hubs 0 and 1 share their leaves 2 to 5, hub 6 has its own leaves 7 to 9.
+        let indices = vec![
+            vec![2, 3, 4, 5],
+            vec![2, 3, 4, 5],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![7, 8, 9],
+            vec![],
+            vec![],
+            vec![],
+        ];
+        let addrs: Vec<NodeAddr> = (0..10).map(addr).collect();
+        let config = EclipseConfig {
+            attacker_nodes: 2,
+            ..Default::default()
+        };
+
+        let report = EclipseReport::new(&addrs, &indices, &[], &config);
+        assert_eq!(report.attacker_nodes, vec![0, 6]);
+
+        // Nodes without an address are skipped.
+        let report = EclipseReport::new(&addrs[..8], &indices, &[], &config);
+        assert_eq!(report.ranking.len(), 8);
+        assert!(report.ranking.iter().all(|exposure| exposure.node < 8));
+    }
+}
@@ -0,0 +1,474 @@
+//! Protocol-agnostic crawler engine.
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use tokio::{
+    task::{self, JoinSet},
+    time::timeout,
+};
+
+use crate::{address::NodeAddr, network::KnownNetwork, summary::NetworkType};
+
+/// Information a node reports about itself during the handshake.
+#[derive(Debug, Default, Clone)]
+pub struct NodeVersion {
+    /// Protocol version reported by the node.
+    pub protocol_version: Option<u32>,
+    /// Software version reported by the node.
+    pub user_agent: Option<String>,
+    /// Network the node belongs to.
+    pub network_type: NetworkType,
+    /// Service flags advertised by the node.
+    pub services: Option<u64>,
+    /// Block height advertised by the node.
+    pub start_height: Option<u64>,
+}
+
+/// Every network crawled by the [`Crawler`] needs to implement this trait.
+#[async_trait]
+pub trait CrawlProtocol: Send + Sync + 'static {
+    /// An established connection to a node.
+    type Connection: Send;
+
+    /// Connects to the node with the given address.
+    async fn connect(&self, addr: NodeAddr) -> io::Result<Self::Connection>;
+
+    /// Performs the handshake and returns the version reported by the node.
+    async fn handshake(&self, connection: &mut Self::Connection) -> io::Result<NodeVersion>;
+
+    /// Requests addresses of the node's peers.
+    async fn get_peers(&self, connection: &mut Self::Connection) -> io::Result<Vec<NodeAddr>>;
+}
+
+/// Crawler configuration.
+#[derive(Debug, Clone)]
+pub struct CrawlerConfig {
+    /// Maximum number of nodes crawled at the same time.
+    pub max_concurrent_connections: usize,
+    /// Time limit for establishing a connection.
+    pub connect_timeout: Duration,
+    /// Time limit for completing the handshake.
+    pub handshake_timeout: Duration,
+    /// Time limit for receiving the peer list.
+    pub peers_timeout: Duration,
+    /// Number of additional attempts made after a failed one.
+    pub max_retries: u8,
+    /// Delay between two attempts of crawling the same node.
+    pub retry_delay: Duration,
+    /// Maximum number of nodes to contact, unlimited if `None`.
+    pub max_nodes: Option<usize>,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_connections: 100,
+            connect_timeout: Duration::from_secs(5),
+            handshake_timeout: Duration::from_secs(10),
+            peers_timeout: Duration::from_secs(10),
+            max_retries: 2,
+            retry_delay: Duration::from_secs(1),
+            max_nodes: None,
+        }
+    }
+}
+
+/// Outcome of crawling a single node.
+struct NodeOutcome {
+    addr: NodeAddr,
+    /// Version reported by the node, `None` if the handshake never succeeded.
+    version: Option<NodeVersion>,
+    /// Time it took to complete the handshake.
+    handshake_latency: Option<Duration>,
+    /// Number of failed attempts.
+    failures: u8,
+    /// Whether an attempt completed, i.e. the peers were received.
+    complete: bool,
+    peers: Vec<NodeAddr>,
+}
+
+/// Breadth-first crawler of a peer-to-peer network.
+pub struct Crawler<P> {
+    protocol: Arc<P>,
+    config: CrawlerConfig,
+}
+
+impl<P: CrawlProtocol> Crawler<P> {
+    pub fn new(protocol: P, config: CrawlerConfig) -> Self {
+        Self {
+            protocol: Arc::new(protocol),
+            config,
+        }
+    }
+
+    /// Crawls the network starting from the given seed nodes.
+    ///
+    /// Use [`KnownNetwork::summary`] on the result to obtain the [`NetworkSummary`](crate::summary::NetworkSummary).
+    pub async fn crawl<I: IntoIterator<Item = NodeAddr>>(&self, seeds: I) -> KnownNetwork {
+        let mut network = KnownNetwork::new();
+        self.crawl_into(&mut network, seeds).await;
+        network
+    }
+
+    /// Crawls the network starting from the given seed nodes and records the results into an
+    /// existing network, e.g. one restored with [`KnownNetwork::load_connections`].
+    pub async fn crawl_into<I: IntoIterator<Item = NodeAddr>>(
+        &self,
+        network: &mut KnownNetwork,
+        seeds: I,
+    ) {
+        let mut frontier: VecDeque<NodeAddr> = VecDeque::new();
+        let mut scheduled: HashSet<NodeAddr> = HashSet::new();
+        for seed in seeds {
+            if scheduled.insert(seed) {
+                frontier.push_back(seed);
+            }
+        }
+
+        let max_concurrent = self.config.max_concurrent_connections.max(1);
+        let mut contacted = 0;
+        let mut tasks = JoinSet::new();
+        let mut crawled: HashMap<task::Id, NodeAddr> = HashMap::new();
+
+        loop {
+            while tasks.len() < max_concurrent
+                && self.config.max_nodes.is_none_or(|max| contacted < max)
+            {
+                let Some(addr) = frontier.pop_front() else {
+                    break;
+                };
+
+                contacted += 1;
+                let protocol = self.protocol.clone();
+                let config = self.config.clone();
+                let task =
+                    tasks.spawn(async move { crawl_node(protocol.as_ref(), &config, addr).await });
+                crawled.insert(task.id(), addr);
+            }
+
+            let Some(result) = tasks.join_next_with_id().await else {
+                break;
+            };
+            let outcome = match result {
+                Ok((id, outcome)) => {
+                    crawled.remove(&id);
+                    outcome
+                }
+                // A panicking protocol implementation only fails the node it was crawling.
+                Err(error) => NodeOutcome {
+                    addr: crawled
+                        .remove(&error.id())
+                        .expect("every task is tracked until it finishes"),
+                    version: None,
+                    handshake_latency: None,
+                    failures: 1,
+                    complete: false,
+                    peers: Vec::new(),
+                },
+            };
+
+            for peer in &outcome.peers {
+                network.touch_connection(outcome.addr, *peer);
+                if scheduled.insert(*peer) {
+                    frontier.push_back(*peer);
+                }
+            }
+            record_outcome(network, outcome);
+        }
+    }
+}
+
+/// Crawls a single node, retrying failed attempts.
+async fn crawl_node<P: CrawlProtocol>(
+    protocol: &P,
+    config: &CrawlerConfig,
+    addr: NodeAddr,
+) -> NodeOutcome {
+    let mut outcome = NodeOutcome {
+        addr,
+        version: None,
+        handshake_latency: None,
+        failures: 0,
+        complete: false,
+        peers: Vec::new(),
+    };
+
+    for attempt in 0..=config.max_retries {
+        if attempt > 0 {
+            tokio::time::sleep(config.retry_delay).await;
+        }
+
+        match crawl_attempt(protocol, config, addr, &mut outcome).await {
+            Ok(()) => {
+                outcome.complete = true;
+                return outcome;
+            }
+            Err(_) => outcome.failures = outcome.failures.saturating_add(1),
+        }
+    }
+
+    outcome
+}
+
+/// Connects to the node, performs the handshake and requests its peers.
+///
+/// The reported version is kept in the outcome even if requesting peers fails afterwards.
+async fn crawl_attempt<P: CrawlProtocol>(
+    protocol: &P,
+    config: &CrawlerConfig,
+    addr: NodeAddr,
+    outcome: &mut NodeOutcome,
+) -> io::Result<()> {
+    let mut connection = with_timeout(config.connect_timeout, protocol.connect(addr)).await?;
+    let started = Instant::now();
+    let version = with_timeout(
+        config.handshake_timeout,
+        protocol.handshake(&mut connection),
+    )
+    .await?;
+    outcome.version = Some(version);
+    outcome.handshake_latency = Some(started.elapsed());
+    outcome.peers = with_timeout(config.peers_timeout, protocol.get_peers(&mut connection)).await?;
+
+    Ok(())
+}
+
+async fn with_timeout<T, F: std::future::Future<Output = io::Result<T>>>(
+    duration: Duration,
+    future: F,
+) -> io::Result<T> {
+    timeout(duration, future)
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+}
+
+/// Stores the outcome of crawling a node into the network.
+///
+/// Failures are only cleared once an attempt completes, a node which completed the handshake
+/// but never sent its peers keeps accumulating them.
+fn record_outcome(network: &mut KnownNetwork, outcome: NodeOutcome) {
+    let node = network.node_mut(outcome.addr);
+    node.connection_failures = match outcome.complete {
+        true => 0,
+        false => node.connection_failures.saturating_add(outcome.failures),
+    };
+
+    match outcome.version {
+        Some(version) => {
+            node.last_connected = Some(SystemTime::now());
+            node.handshake_successful = true;
+            node.protocol_version = version.protocol_version;
+            node.user_agent = version.user_agent;
+            node.network_type = version.network_type;
+            node.services = version.services;
+            node.start_height = version.start_height;
+            node.handshake_latency = outcome.handshake_latency;
+        }
+        None => node.handshake_successful = false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::testing;
+
+    /// A line-based protocol spoken by the fake nodes.
+    struct FakeProtocol;
+
+    #[async_trait]
+    impl CrawlProtocol for FakeProtocol {
+        type Connection = BufReader<TcpStream>;
+
+        async fn connect(&self, addr: NodeAddr) -> io::Result<Self::Connection> {
+            let addr = addr
+                .socket_addr()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::Unsupported))?;
+            Ok(BufReader::new(TcpStream::connect(addr).await?))
+        }
+
+        async fn handshake(&self, connection: &mut Self::Connection) -> io::Result<NodeVersion> {
+            let reply = request(connection, "VERSION").await?;
+            let (version, user_agent) = reply
+                .split_once(' ')
+                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+
+            Ok(NodeVersion {
+                protocol_version: version.parse().ok(),
+                user_agent: Some(user_agent.to_owned()),
+                network_type: NetworkType::Zcash,
+                ..Default::default()
+            })
+        }
+
+        async fn get_peers(&self, connection: &mut Self::Connection) -> io::Result<Vec<NodeAddr>> {
+            let reply = request(connection, "GETADDR").await?;
+            Ok(reply
+                .split(',')
+                .filter_map(|addr| addr.parse().ok())
+                .collect())
+        }
+    }
+
+    async fn request(connection: &mut BufReader<TcpStream>, message: &str) -> io::Result<String> {
+        connection
+            .get_mut()
+            .write_all(format!("{message}\n").as_bytes())
+            .await?;
+        let mut reply = String::new();
+        if connection.read_line(&mut reply).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(reply.trim_end().to_owned())
+    }
+
+    /// Starts a fake node which advertises the given peers, or drops the connection when asked
+    /// for them if `None`.
+    fn serve(listener: TcpListener, peers: Option<Vec<NodeAddr>>) {
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let peers = peers.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    let mut line = String::new();
+                    while stream.read_line(&mut line).await.unwrap_or_default() > 0 {
+                        let reply = match line.trim_end() {
+                            "VERSION" => "170100 /Fake:1.0.0/".to_owned(),
+                            "GETADDR" => match &peers {
+                                Some(peers) => peers
+                                    .iter()
+                                    .map(|addr| addr.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(","),
+                                None => return,
+                            },
+                            _ => return,
+                        };
+                        let _ = stream
+                            .get_mut()
+                            .write_all(format!("{reply}\n").as_bytes())
+                            .await;
+                        line.clear();
+                    }
+                });
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn should_crawl_fake_network() {
+        let mut listeners = Vec::new();
+        for _ in 0..4 {
+            listeners.push(TcpListener::bind("127.0.0.1:0").await.unwrap());
+        }
+        let addrs: Vec<NodeAddr> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().into())
+            .collect();
+
+        // Reserve an address nobody listens on.
+        let dead = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            NodeAddr::from(listener.local_addr().unwrap())
+        };
+
+        // A ring 0 - 1 - 2 - 3 - 0, node 3 also advertises the dead address.
+        let peers: HashMap<usize, Vec<NodeAddr>> = HashMap::from([
+            (0, vec![addrs[1], addrs[3]]),
+            (1, vec![addrs[0], addrs[2]]),
+            (2, vec![addrs[1], addrs[3]]),
+            (3, vec![addrs[2], addrs[0], dead]),
+        ]);
+        for (index, listener) in listeners.into_iter().enumerate() {
+            serve(listener, Some(peers[&index].clone()));
+        }
+
+        let config = CrawlerConfig {
+            max_concurrent_connections: 2,
+            connect_timeout: Duration::from_secs(1),
+            retry_delay: Duration::from_millis(10),
+            max_retries: 1,
+            ..Default::default()
+        };
+        let network = Crawler::new(FakeProtocol, config).crawl([addrs[0]]).await;
+
+        assert_eq!(network.node(&dead).unwrap().connection_failures, 2);
+        assert!(!network.node(&dead).unwrap().handshake_successful);
+
+        let summary = network.summary();
+        assert_eq!(summary.num_known_nodes, 5);
+        assert_eq!(summary.num_good_nodes, 4);
+        assert_eq!(summary.num_known_connections, 5);
+        assert_eq!(summary.protocol_versions.get(&170100), Some(&4));
+        assert_eq!(summary.user_agents.get("/Fake:1.0.0/"), Some(&4));
+        assert!(summary.nodes_indices.iter().all(|list| list.len() == 2));
+        assert!(summary
+            .node_records
+            .iter()
+            .all(|record| record.handshake_latency.is_some() && record.first_seen.is_some()));
+    }
+
+    /// Panics when connecting to any node but the seed.
+    struct PanickingProtocol(NodeAddr);
+
+    #[async_trait]
+    impl CrawlProtocol for PanickingProtocol {
+        type Connection = ();
+
+        async fn connect(&self, addr: NodeAddr) -> io::Result<Self::Connection> {
+            assert_eq!(addr, self.0, "unexpected node");
+            Ok(())
+        }
+
+        async fn handshake(&self, _connection: &mut Self::Connection) -> io::Result<NodeVersion> {
+            Ok(NodeVersion::default())
+        }
+
+        async fn get_peers(&self, _connection: &mut Self::Connection) -> io::Result<Vec<NodeAddr>> {
+            Ok(vec![testing::addr(4)])
+        }
+    }
+
+    #[tokio::test]
+    async fn should_record_failed_peer_requests_and_panics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = NodeAddr::from(listener.local_addr().unwrap());
+        serve(listener, None);
+
+        let config = CrawlerConfig {
+            retry_delay: Duration::from_millis(10),
+            max_retries: 1,
+            ..Default::default()
+        };
+        let network = Crawler::new(FakeProtocol, config.clone())
+            .crawl([addr])
+            .await;
+
+        // The handshake succeeded, but the peers were never received.
+        let node = network.node(&addr).unwrap();
+        assert!(node.handshake_successful);
+        assert_eq!(node.protocol_version, Some(170100));
+        assert_eq!(node.connection_failures, 2);
+
+        let network = Crawler::new(PanickingProtocol(addr), config)
+            .crawl([addr])
+            .await;
+
+        let peer = network.node(&testing::addr(4)).unwrap();
+        assert!(!peer.handshake_successful);
+        assert_eq!(peer.connection_failures, 1);
+        assert_eq!(network.node(&addr).unwrap().connection_failures, 0);
+    }
+}
@@ -0,0 +1,342 @@
+//! Exporters of the crawled network topology to common graph formats.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{graph::adjacency_sets, summary::NetworkSummary};
+
+/// Additional per-node attributes supplied by the caller.
+///
+/// Map: Attribute name -> attribute values. Values are indexed the same way as
+/// [`NetworkSummary::node_addrs`], nodes without a value get an empty one.
+pub type NodeAttributes = BTreeMap<String, Vec<String>>;
+
+/// Supported graph export formats.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum GraphFormat {
+    /// GraphML, as used by graph-tool, networkx, yEd and others.
+    GraphMl,
+    /// GEXF, the native format of Gephi.
+    Gexf,
+    /// Graphviz DOT.
+    Dot,
+}
+
+impl NetworkSummary {
+    /// Writes the network topology in the given format.
+    ///
+    /// Attributes can't use the names of the built-in ones: `address`, `network_type` and
+    /// `degree`.
+    pub fn export_graph<W: Write>(
+        &self,
+        format: GraphFormat,
+        attributes: &NodeAttributes,
+        writer: W,
+    ) -> io::Result<()> {
+        let nodes = ExportNodes::new(self, attributes)?;
+
+        match format {
+            GraphFormat::GraphMl => write_graphml(&nodes, writer),
+            GraphFormat::Gexf => write_gexf(&nodes, writer),
+            GraphFormat::Dot => write_dot(&nodes, writer),
+        }
+    }
+
+    /// Writes the network topology in the given format to file.
+    pub fn export_graph_to_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: GraphFormat,
+        attributes: &NodeAttributes,
+    ) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.export_graph(format, attributes, &mut writer)?;
+        writer.flush()
+    }
+}
+
+/// Node attributes and edges prepared for export.
+struct ExportNodes {
+    /// Attribute names, including the built-in ones.
+    names: Vec<String>,
+    /// Attribute values of each node, indexed like `names`.
+    values: Vec<Vec<String>>,
+    /// Unique undirected edges.
+    edges: Vec<(usize, usize)>,
+}
+
+/// Names of the attributes exported for every node.
+const ADDRESS: &str = "address";
+const NETWORK_TYPE: &str = "network_type";
+const DEGREE: &str = "degree";
+const BUILT_IN: [&str; 3] = [ADDRESS, NETWORK_TYPE, DEGREE];
+
+impl ExportNodes {
+    fn new(summary: &NetworkSummary, attributes: &NodeAttributes) -> io::Result<Self> {
+        if let Some(name) = attributes
+            .keys()
+            .find(|name| BUILT_IN.contains(&name.as_str()))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("attribute name is reserved: {name}"),
+            ));
+        }
+
+        let adjacency = adjacency_sets(&summary.nodes_indices);
+        let num_nodes = summary.node_addrs.len().max(adjacency.len());
+
+        let mut names: Vec<String> = BUILT_IN.iter().map(|name| name.to_string()).collect();
+        names.extend(attributes.keys().cloned());
+
+        let values = (0..num_nodes)
+            .map(|node| {
+                let mut values = vec![
+                    summary
+                        .node_addrs
+                        .get(node)
+                        .map(|addr| addr.to_string())
+                        .unwrap_or_default(),
+                    summary
+                        .node_network_types
+                        .get(node)
+                        .map(|network| network.to_string())
+                        .unwrap_or_default(),
+                    adjacency
+                        .get(node)
+                        .map(|set| set.len())
+                        .unwrap_or_default()
+                        .to_string(),
+                ];
+                values.extend(
+                    attributes
+                        .values()
+                        .map(|list| list.get(node).cloned().unwrap_or_default()),
+                );
+                values
+            })
+            .collect();
+
+        let mut edges: Vec<(usize, usize)> = adjacency
+            .iter()
+            .enumerate()
+            .flat_map(|(a, set)| set.iter().filter(move |b| a < **b).map(move |b| (a, *b)))
+            .collect();
+        edges.sort_unstable();
+
+        Ok(Self {
+            names,
+            values,
+            edges,
+        })
+    }
+
+    /// Returns the type of the attribute at the given position, as named by the format.
+    fn attribute_type(&self, index: usize, format: GraphFormat) -> &'static str {
+        match format {
+            _ if self.names[index] != DEGREE => "string",
+            GraphFormat::Gexf => "integer",
+            _ => "int",
+        }
+    }
+}
+
+fn write_graphml<W: Write>(nodes: &ExportNodes, mut w: W) -> io::Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">"#
+    )?;
+
+    for (id, name) in nodes.names.iter().enumerate() {
+        writeln!(
+            w,
+            r#"  <key id="d{id}" for="node" attr.name="{}" attr.type="{}"/>"#,
+            escape_xml(name),
+            nodes.attribute_type(id, GraphFormat::GraphMl)
+        )?;
+    }
+
+    writeln!(w, r#"  <graph id="G" edgedefault="undirected">"#)?;
+    for (node, values) in nodes.values.iter().enumerate() {
+        writeln!(w, r#"    <node id="n{node}">"#)?;
+        for (id, value) in values.iter().enumerate() {
+            writeln!(w, r#"      <data key="d{id}">{}</data>"#, escape_xml(value))?;
+        }
+        writeln!(w, "    </node>")?;
+    }
+    for (a, b) in &nodes.edges {
+        writeln!(w, r#"    <edge source="n{a}" target="n{b}"/>"#)?;
+    }
+    writeln!(w, "  </graph>")?;
+    writeln!(w, "</graphml>")?;
+
+    Ok(())
+}
+
+fn write_gexf<W: Write>(nodes: &ExportNodes, mut w: W) -> io::Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(w, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+    writeln!(w, r#"  <graph mode="static" defaultedgetype="undirected">"#)?;
+
+    writeln!(w, r#"    <attributes class="node">"#)?;
+    for (id, name) in nodes.names.iter().enumerate() {
+        writeln!(
+            w,
+            r#"      <attribute id="{id}" title="{}" type="{}"/>"#,
+            escape_xml(name),
+            nodes.attribute_type(id, GraphFormat::Gexf)
+        )?;
+    }
+    writeln!(w, "    </attributes>")?;
+
+    writeln!(w, "    <nodes>")?;
+    for (node, values) in nodes.values.iter().enumerate() {
+        writeln!(
+            w,
+            r#"      <node id="{node}" label="{}">"#,
+            escape_xml(&values[0])
+        )?;
+        writeln!(w, "        <attvalues>")?;
+        for (id, value) in values.iter().enumerate() {
+            writeln!(
+                w,
+                r#"          <attvalue for="{id}" value="{}"/>"#,
+                escape_xml(value)
+            )?;
+        }
+        writeln!(w, "        </attvalues>")?;
+        writeln!(w, "      </node>")?;
+    }
+    writeln!(w, "    </nodes>")?;
+
+    writeln!(w, "    <edges>")?;
+    for (id, (a, b)) in nodes.edges.iter().enumerate() {
+        writeln!(w, r#"      <edge id="{id}" source="{a}" target="{b}"/>"#)?;
+    }
+    writeln!(w, "    </edges>")?;
+
+    writeln!(w, "  </graph>")?;
+    writeln!(w, "</gexf>")?;
+
+    Ok(())
+}
+
+fn write_dot<W: Write>(nodes: &ExportNodes, mut w: W) -> io::Result<()> {
+    writeln!(w, "graph network {{")?;
+    for (node, values) in nodes.values.iter().enumerate() {
+        let attributes: Vec<String> = nodes
+            .names
+            .iter()
+            .zip(values)
+            .map(|(name, value)| format!("\"{}\"=\"{}\"", escape_dot(name), escape_dot(value)))
+            .collect();
+        writeln!(
+            w,
+            "  {node} [label=\"{}\", {}];",
+            escape_dot(&values[0]),
+            attributes.join(", ")
+        )?;
+    }
+    for (a, b) in &nodes.edges {
+        writeln!(w, "  {a} -- {b};")?;
+    }
+    writeln!(w, "}}")?;
+
+    Ok(())
+}
+
+/// Escapes markup and replaces characters which XML 1.0 doesn't allow, such as most control
+/// characters, with U+FFFD.
+fn escape_xml(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '\t' | '\n' | '\r' => output.push(c),
+            '\0'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => output.push(char::REPLACEMENT_CHARACTER),
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&apos;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+fn escape_dot(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{summary::NetworkType, testing::addr};
+
+    fn summary() -> NetworkSummary {
+        NetworkSummary {
+            node_addrs: vec![addr(4), addr(5)],
+            node_network_types: vec![NetworkType::Zcash, NetworkType::Zcash],
+            nodes_indices: vec![vec![1], vec![0]],
+            ..Default::default()
+        }
+    }
+
+    fn export(format: GraphFormat) -> String {
+        let attributes = NodeAttributes::from([(
+            "agent".to_owned(),
+            vec!["/MagicBean:5.4.2/".to_owned(), "<odd>".to_owned()],
+        )]);
+        let mut output = Vec::new();
+        summary()
+            .export_graph(format, &attributes, &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn should_export_graphml() {
+        let output = export(GraphFormat::GraphMl);
+
+        assert!(output.contains(r#"attr.name="degree" attr.type="int""#));
+        assert!(output.contains(r#"<data key="d0">1.2.3.4:8233</data>"#));
+        assert!(output.contains(r#"<data key="d3">&lt;odd&gt;</data>"#));
+        assert_eq!(output.matches("<edge ").count(), 1);
+    }
+
+    #[test]
+    fn should_export_gexf_and_dot() {
+        let gexf = export(GraphFormat::Gexf);
+        assert!(gexf.contains(r#"title="degree" type="integer""#));
+        assert!(gexf.contains(r#"<attvalue for="1" value="Zcash"/>"#));
+        assert!(gexf.contains(r#"<edge id="0" source="0" target="1"/>"#));
+
+        let dot = export(GraphFormat::Dot);
+        assert!(dot.starts_with("graph network {"));
+        assert!(dot.contains(r#"0 [label="1.2.3.4:8233", "address"="1.2.3.4:8233""#));
+        assert!(dot.contains("0 -- 1;"));
+    }
+
+    #[test]
+    fn should_sanitize_hostile_attributes() {
+        let attributes = NodeAttributes::from([(
+            "agent".to_owned(),
+            vec!["/Evil\u{1}\u{ffff}:1.0/".to_owned()],
+        )]);
+        let mut output = Vec::new();
+        summary()
+            .export_graph(GraphFormat::GraphMl, &attributes, &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("<data key=\"d3\">/Evil\u{fffd}\u{fffd}:1.0/</data>"));
+
+        let reserved = NodeAttributes::from([(DEGREE.to_owned(), vec![])]);
+        assert!(summary()
+            .export_graph(GraphFormat::Gexf, &reserved, io::sink())
+            .is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Geolocation of crawled nodes.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    net::IpAddr,
+};
+
+use serde::{Deserialize, Serialize};
+use ziggurat_core_geoip::{
+    coordinates::Coordinates,
+    geoip::{GeoIPService, GeoInfo},
+};
+
+use crate::summary::{print_hashmap, NetworkSummary};
+
+/// Label used for nodes whose geo field is missing.
+const UNKNOWN: &str = "Unknown";
+
+/// Network summary annotated with geolocation data of its nodes.
+#[derive(Default, Clone, Deserialize, Serialize)]
+pub struct GeoSummary {
+    /// The annotated network summary.
+    pub summary: NetworkSummary,
+    /// Geo information of good nodes, `None` if the lookup failed or the node isn't an IP node.
+    /// Indexes correspond to `node_addrs`.
+    pub node_geo_info: Vec<Option<GeoInfo>>,
+    /// Number of unique IP addresses whose lookup failed.
+    pub num_failed_lookups: usize,
+    /// Map: Country -> number of nodes located in this country.
+    pub countries: HashMap<String, usize>,
+    /// Map: City -> number of nodes located in this city.
+    pub cities: HashMap<String, usize>,
+    /// Map: Timezone -> number of nodes located in this timezone.
+    pub timezones: HashMap<String, usize>,
+    /// Map: ISP -> number of nodes hosted by this ISP.
+    pub isps: HashMap<String, usize>,
+}
+
+impl GeoSummary {
+    /// Looks up all good nodes of the summary using the given service.
+    ///
+    /// Nodes sharing the same IP address are looked up only once, overlay network nodes aren't
+    /// looked up at all.
+    pub async fn new<S: GeoIPService + ?Sized>(summary: &NetworkSummary, service: &S) -> Self {
+        let unique_ips: HashSet<IpAddr> = summary
+            .node_addrs
+            .iter()
+            .filter_map(|addr| addr.ip())
+            .collect();
+
+        let mut lookups = HashMap::with_capacity(unique_ips.len());
+        let mut num_failed_lookups = 0;
+        for ip in unique_ips {
+            match service.lookup(ip).await {
+                Ok(info) => {
+                    lookups.insert(ip, info.geo_info);
+                }
+                Err(_) => num_failed_lookups += 1,
+            }
+        }
+
+        let mut geo_summary = Self {
+            summary: summary.clone(),
+            num_failed_lookups,
+            ..Default::default()
+        };
+
+        for addr in &summary.node_addrs {
+            let geo_info = addr.ip().and_then(|ip| lookups.get(&ip)).cloned();
+
+            if let Some(info) = &geo_info {
+                count(&mut geo_summary.countries, &info.country);
+                count(&mut geo_summary.cities, &info.city);
+                count(&mut geo_summary.timezones, &info.timezone);
+                count(&mut geo_summary.isps, &info.isp);
+            }
+            geo_summary.node_geo_info.push(geo_info);
+        }
+
+        geo_summary
+    }
+
+    /// Returns the coordinates of each node, `None` if unknown.
+    /// Indexes correspond to `node_addrs`.
+    pub fn node_coordinates(&self) -> Vec<Option<Coordinates>> {
+        self.node_geo_info
+            .iter()
+            .map(|info| info.as_ref().and_then(|info| info.coordinates))
+            .collect()
+    }
+}
+
+impl NetworkSummary {
+    /// Annotates the summary with geolocation data obtained from the given service.
+    pub async fn geolocate<S: GeoIPService + ?Sized>(&self, service: &S) -> GeoSummary {
+        GeoSummary::new(self, service).await
+    }
+}
+
+fn count(counts: &mut HashMap<String, usize>, field: &Option<String>) {
+    let key = match field {
+        Some(value) if !value.is_empty() => value.clone(),
+        _ => UNKNOWN.to_owned(),
+    };
+    *counts.entry(key).or_default() += 1;
+}
+
+impl fmt::Display for GeoSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.summary)?;
+
+        writeln!(f, "\nGeolocation summary:\n")?;
+        writeln!(
+            f,
+            "Failed to look up {} IP address(es)",
+            self.num_failed_lookups
+        )?;
+
+        writeln!(f, "\nCountries:")?;
+        print_hashmap(f, &self.countries)?;
+        writeln!(f, "\nCities:")?;
+        print_hashmap(f, &self.cities)?;
+        writeln!(f, "\nTimezones:")?;
+        print_hashmap(f, &self.timezones)?;
+        writeln!(f, "\nISPs:")?;
+        print_hashmap(f, &self.isps)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+    use ziggurat_core_geoip::{
+        geoip::GeoIPInfo,
+        providers::testing::{TestingProvider, TestingService},
+    };
+
+    use super::*;
+    use crate::testing::addr;
+
+    /// Fails for every IPv6 address.
+    struct Ipv4OnlyService;
+
+    #[async_trait]
+    impl GeoIPService for Ipv4OnlyService {
+        async fn lookup(&self, ip: IpAddr) -> Result<GeoIPInfo, String> {
+            match ip {
+                IpAddr::V4(_) => {
+                    TestingService::new(TestingProvider::Zeroed)
+                        .lookup(ip)
+                        .await
+                }
+                IpAddr::V6(_) => Err("unsupported address".to_owned()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn should_count_nodes_and_failed_lookups() {
+        let summary = NetworkSummary {
+            node_addrs: vec![addr(4), addr(5), "[::1]:8233".parse().unwrap()],
+            ..Default::default()
+        };
+
+        let geo_summary = summary.geolocate(&Ipv4OnlyService).await;
+
+        assert_eq!(geo_summary.num_failed_lookups, 1);
+        assert_eq!(geo_summary.countries.get(UNKNOWN), Some(&2));
+        assert!(geo_summary.node_geo_info[0].is_some());
+        assert!(geo_summary.node_geo_info[2].is_none());
+        assert!(geo_summary
+            .to_string()
+            .contains("Failed to look up 1 IP address(es)"));
+    }
+}
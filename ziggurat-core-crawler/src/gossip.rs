@@ -0,0 +1,250 @@
+//! Discrete-event simulation of message propagation over the crawled topology.
+use std::{cmp::Reverse, collections::BinaryHeap, fmt, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "geoip")]
+use ziggurat_core_geoip::coordinates::Coordinates;
+
+use crate::{
+    address::NodeAddr,
+    graph::{adjacency_sets, sorted_neighbours},
+    summary::{NetworkSummary, NodesIndices},
+};
+
+/// Delay of a single hop between two neighbouring nodes.
+///
+/// Non-exhaustive, as geographic delays are only available with the `geoip` feature.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[non_exhaustive]
+pub enum HopDelay {
+    /// Every hop takes the same time.
+    Fixed(Duration),
+    /// Each hop takes a time drawn uniformly from the range.
+    Uniform { min: Duration, max: Duration },
+    /// Each hop takes `base` plus the time a signal travelling at `meters_per_second` needs to
+    /// cover the distance between the nodes.
+    #[cfg(feature = "geoip")]
+    Geographic {
+        /// Coordinates of the nodes, indexes correspond to `node_addrs`, see
+        /// [`GeoSummary::node_coordinates`](crate::geo::GeoSummary::node_coordinates).
+        coordinates: Vec<Option<Coordinates>>,
+        base: Duration,
+        meters_per_second: f64,
+        /// Delay of hops from or to nodes without coordinates.
+        fallback: Duration,
+    },
+}
+
+impl HopDelay {
+    #[cfg_attr(not(feature = "geoip"), allow(unused_variables))]
+    fn sample(&self, from: usize, to: usize, rng: &mut StdRng) -> Duration {
+        match self {
+            HopDelay::Fixed(delay) => *delay,
+            HopDelay::Uniform { min, max } if max > min => rng.gen_range(*min..=*max),
+            HopDelay::Uniform { min, .. } => *min,
+            #[cfg(feature = "geoip")]
+            HopDelay::Geographic {
+                coordinates,
+                base,
+                meters_per_second,
+                fallback,
+            } => match (
+                coordinates.get(from).copied().flatten(),
+                coordinates.get(to).copied().flatten(),
+            ) {
+                (Some(a), Some(b)) => {
+                    Duration::try_from_secs_f64(a.distance_to(b) / meters_per_second)
+                        .map_or(*fallback, |travel| *base + travel)
+                }
+                _ => *fallback,
+            },
+        }
+    }
+}
+
+/// Gossip simulation parameters.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GossipConfig {
+    /// Delay of each hop.
+    pub delay: HopDelay,
+    /// Seed of the random delays.
+    pub seed: u64,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        Self {
+            delay: HopDelay::Fixed(Duration::from_millis(100)),
+            seed: 0,
+        }
+    }
+}
+
+/// Outcome of a simulated message propagation.
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct GossipReport {
+    /// Index of the node the message originated from.
+    pub origin: usize,
+    /// Time the message reached each node, `None` if it never did.
+    /// Indexes correspond to `node_addrs`.
+    pub arrival_times: Vec<Option<Duration>>,
+    /// Time to reach half of all nodes, `None` if never reached.
+    pub time_to_50: Option<Duration>,
+    /// Time to reach 90% of all nodes, `None` if never reached.
+    pub time_to_90: Option<Duration>,
+    /// Time to reach all nodes, `None` if some nodes are never reached.
+    pub time_to_100: Option<Duration>,
+    /// Indexes of the nodes the message never reached.
+    pub unreached: Vec<usize>,
+}
+
+impl GossipReport {
+    /// Simulates a message spreading from the origin, each node forwarding it to all of its
+    /// neighbours as soon as it first receives it.
+    pub fn new(
+        indices: &NodesIndices,
+        origin: usize,
+        config: &GossipConfig,
+    ) -> Result<Self, String> {
+        let neighbours = sorted_neighbours(&adjacency_sets(indices));
+        if origin >= neighbours.len() {
+            return Err(format!("origin {origin} is not a node of the graph"));
+        }
+
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let mut arrival_times = vec![None; neighbours.len()];
+        let mut events = BinaryHeap::from([Reverse((Duration::ZERO, origin))]);
+
+        while let Some(Reverse((time, node))) = events.pop() {
+            if arrival_times[node].is_some() {
+                continue;
+            }
+            arrival_times[node] = Some(time);
+
+            for &peer in &neighbours[node] {
+                if arrival_times[peer].is_none() {
+                    let delay = config.delay.sample(node, peer, &mut rng);
+                    events.push(Reverse((time + delay, peer)));
+                }
+            }
+        }
+
+        let mut report = Self {
+            origin,
+            unreached: (0..arrival_times.len())
+                .filter(|node| arrival_times[*node].is_none())
+                .collect(),
+            arrival_times,
+            ..Default::default()
+        };
+        report.time_to_50 = report.time_to_reach(0.5);
+        report.time_to_90 = report.time_to_reach(0.9);
+        report.time_to_100 = report.time_to_reach(1.0);
+
+        Ok(report)
+    }
+
+    /// Returns the time it took to reach the given fraction of all nodes, `None` if the
+    /// fraction was never reached.
+    pub fn time_to_reach(&self, fraction: f64) -> Option<Duration> {
+        let mut times: Vec<Duration> = self.arrival_times.iter().flatten().copied().collect();
+        times.sort_unstable();
+
+        let needed = (fraction.clamp(0.0, 1.0) * self.arrival_times.len() as f64).ceil() as usize;
+        times.get(needed.max(1) - 1).copied()
+    }
+}
+
+impl NetworkSummary {
+    /// Simulates a message spreading from the given node through the crawled topology.
+    pub fn simulate_gossip(
+        &self,
+        origin: &NodeAddr,
+        config: &GossipConfig,
+    ) -> Result<GossipReport, String> {
+        let origin = self
+            .node_addrs
+            .iter()
+            .position(|addr| addr == origin)
+            .ok_or_else(|| format!("unknown node: {origin}"))?;
+
+        GossipReport::new(&self.nodes_indices, origin, config)
+    }
+}
+
+impl fmt::Display for GossipReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = |time: Option<Duration>| match time {
+            Some(time) => format!("{:.3}s", time.as_secs_f64()),
+            None => "never".to_owned(),
+        };
+
+        writeln!(f, "Gossip simulation from node {}:\n", self.origin)?;
+        writeln!(
+            f,
+            "Reached {} of {} node(s)",
+            self.arrival_times.len() - self.unreached.len(),
+            self.arrival_times.len()
+        )?;
+        writeln!(f, "50% of nodes reached after {}", format(self.time_to_50))?;
+        writeln!(f, "90% of nodes reached after {}", format(self.time_to_90))?;
+        writeln!(f, "All nodes reached after {}", format(self.time_to_100))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_time_propagation() {
+        // A path 0 - 1 - 2 - 3 and an isolated node 4.
+        let indices = vec![vec![1], vec![0, 2], vec![1, 3], vec![2], vec![]];
+        let config = GossipConfig {
+            delay: HopDelay::Fixed(Duration::from_secs(1)),
+            ..Default::default()
+        };
+
+        let report = GossipReport::new(&indices, 0, &config).unwrap();
+
+        assert_eq!(report.arrival_times[3], Some(Duration::from_secs(3)));
+        assert_eq!(report.time_to_50, Some(Duration::from_secs(2)));
+        assert_eq!(report.time_to_90, None);
+        assert_eq!(report.time_to_100, None);
+        assert_eq!(report.time_to_reach(0.8), Some(Duration::from_secs(3)));
+        assert_eq!(report.unreached, vec![4]);
+        assert!(GossipReport::new(&indices, 5, &config).is_err());
+    }
+
+    #[cfg(feature = "geoip")]
+    #[test]
+    fn should_derive_delays_from_distances() {
+        let coordinates = vec![
+            Some(Coordinates::new(0.0, 0.0)),
+            Some(Coordinates::new(0.0, 1.0)),
+            None,
+        ];
+        let distance = coordinates[0].unwrap().distance_to(coordinates[1].unwrap());
+        let config = GossipConfig {
+            delay: HopDelay::Geographic {
+                coordinates,
+                base: Duration::from_millis(10),
+                meters_per_second: 1e5,
+                fallback: Duration::from_secs(5),
+            },
+            ..Default::default()
+        };
+
+        // Triangle: the node without coordinates is reached directly after the fallback delay.
+        let indices = vec![vec![1, 2], vec![0, 2], vec![0, 1]];
+        let report = GossipReport::new(&indices, 0, &config).unwrap();
+
+        let expected = Duration::from_millis(10) + Duration::from_secs_f64(distance / 1e5);
+        assert_eq!(report.arrival_times[1], Some(expected));
+        assert_eq!(report.arrival_times[2], Some(Duration::from_secs(5)));
+        assert_eq!(report.time_to_100, Some(Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,347 @@
+//! Graph analytics computed over the crawled network topology.
+use std::{
+    cmp,
+    collections::{BTreeMap, HashSet, VecDeque},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::summary::{NetworkSummary, NodesIndices};
+
+/// Maximum number of power iterations used when computing eigenvector centrality.
+const EIGENVECTOR_MAX_ITERATIONS: usize = 1000;
+/// Convergence threshold used when computing eigenvector centrality.
+const EIGENVECTOR_TOLERANCE: f64 = 1e-9;
+
+/// Graph metrics of a crawled network.
+///
+/// All per-node vectors are indexed the same way as [`NetworkSummary::node_addrs`].
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct GraphMetrics {
+    /// Number of nodes in the graph.
+    pub num_nodes: usize,
+    /// Number of unique undirected edges in the graph.
+    pub num_edges: usize,
+    /// Map: Degree -> number of nodes with this degree.
+    pub degree_distribution: BTreeMap<usize, usize>,
+    /// Average node degree.
+    pub average_degree: f64,
+    /// Average local clustering coefficient.
+    pub average_clustering: f64,
+    /// Connected components, sorted from the largest to the smallest.
+    pub components: Vec<Vec<usize>>,
+    /// The longest shortest path found within any of the components.
+    pub diameter: usize,
+    /// Average shortest path length between all pairs of connected nodes.
+    pub average_path_length: f64,
+    /// Normalized betweenness centrality of each node.
+    pub betweenness: Vec<f64>,
+    /// Closeness centrality of each node, scaled by the size of its component.
+    pub closeness: Vec<f64>,
+    /// Eigenvector centrality of each node.
+    pub eigenvector: Vec<f64>,
+}
+
+impl GraphMetrics {
+    /// Computes all metrics for the given adjacency list.
+    ///
+    /// The graph is treated as undirected, self connections and out-of-range indices are ignored.
+    pub fn new(indices: &NodesIndices) -> Self {
+        let n = indices.len();
+        let adjacency = adjacency_sets(indices);
+        let degrees: Vec<usize> = adjacency.iter().map(|set| set.len()).collect();
+
+        let mut degree_distribution = BTreeMap::new();
+        for degree in &degrees {
+            *degree_distribution.entry(*degree).or_default() += 1;
+        }
+
+        let num_edges = degrees.iter().sum::<usize>() / 2;
+        let average_degree = if n == 0 {
+            0.0
+        } else {
+            degrees.iter().sum::<usize>() as f64 / n as f64
+        };
+
+        let average_clustering = if n == 0 {
+            0.0
+        } else {
+            (0..n)
+                .map(|node| local_clustering(&adjacency, node))
+                .sum::<f64>()
+                / n as f64
+        };
+
+        let neighbours = sorted_neighbours(&adjacency);
+
+        let paths = ShortestPaths::new(&neighbours);
+
+        Self {
+            num_nodes: n,
+            num_edges,
+            degree_distribution,
+            average_degree,
+            average_clustering,
+            components: connected_components(indices),
+            diameter: paths.diameter,
+            average_path_length: paths.average_length,
+            betweenness: paths.betweenness,
+            closeness: paths.closeness,
+            eigenvector: eigenvector_centrality(&neighbours),
+        }
+    }
+}
+
+impl NetworkSummary {
+    /// Computes graph metrics of the crawled network.
+    pub fn graph_metrics(&self) -> GraphMetrics {
+        GraphMetrics::new(&self.nodes_indices)
+    }
+}
+
+/// Returns the connected components of the graph, sorted from the largest to the smallest.
+pub fn connected_components(indices: &NodesIndices) -> Vec<Vec<usize>> {
+    let n = indices.len();
+    let adjacency = adjacency_sets(indices);
+    let mut visited = vec![false; n];
+    let mut components = Vec::new();
+
+    for start in 0..n {
+        if visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        let mut component = vec![start];
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(node) = queue.pop_front() {
+            for &next in &adjacency[node] {
+                if !visited[next] {
+                    visited[next] = true;
+                    component.push(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        component.sort_unstable();
+        components.push(component);
+    }
+
+    components.sort_by_key(|component| cmp::Reverse(component.len()));
+    components
+}
+
+/// Builds a symmetric, deduplicated adjacency without self loops and out-of-range indices.
+pub(crate) fn adjacency_sets(indices: &NodesIndices) -> Vec<HashSet<usize>> {
+    let mut adjacency = vec![HashSet::new(); indices.len()];
+
+    for (node, neighbours) in indices.iter().enumerate() {
+        for &neighbour in neighbours {
+            if neighbour != node && neighbour < indices.len() {
+                adjacency[node].insert(neighbour);
+                adjacency[neighbour].insert(node);
+            }
+        }
+    }
+
+    adjacency
+}
+
+/// Converts adjacency sets into sorted neighbour lists.
+pub(crate) fn sorted_neighbours(adjacency: &[HashSet<usize>]) -> Vec<Vec<usize>> {
+    adjacency
+        .iter()
+        .map(|set| {
+            let mut list: Vec<usize> = set.iter().copied().collect();
+            list.sort_unstable();
+            list
+        })
+        .collect()
+}
+
+/// Local clustering coefficient of a single node.
+fn local_clustering(adjacency: &[HashSet<usize>], node: usize) -> f64 {
+    let neighbours: Vec<usize> = adjacency[node].iter().copied().collect();
+    let degree = neighbours.len();
+    if degree < 2 {
+        return 0.0;
+    }
+
+    let mut links = 0;
+    for (i, a) in neighbours.iter().enumerate() {
+        for b in &neighbours[i + 1..] {
+            if adjacency[*a].contains(b) {
+                links += 1;
+            }
+        }
+    }
+
+    2.0 * links as f64 / (degree * (degree - 1)) as f64
+}
+
+/// Results of the all-pairs BFS (Brandes' algorithm).
+struct ShortestPaths {
+    diameter: usize,
+    average_length: f64,
+    betweenness: Vec<f64>,
+    closeness: Vec<f64>,
+}
+
+impl ShortestPaths {
+    fn new(neighbours: &[Vec<usize>]) -> Self {
+        let n = neighbours.len();
+        let mut betweenness = vec![0.0; n];
+        let mut closeness = vec![0.0; n];
+        let mut diameter = 0;
+        let mut total_length = 0usize;
+        let mut num_paths = 0usize;
+
+        for source in 0..n {
+            let mut stack = Vec::with_capacity(n);
+            let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); n];
+            let mut sigma = vec![0.0f64; n];
+            let mut distance: Vec<Option<usize>> = vec![None; n];
+            sigma[source] = 1.0;
+            distance[source] = Some(0);
+
+            let mut queue = VecDeque::from([source]);
+            while let Some(node) = queue.pop_front() {
+                stack.push(node);
+                let node_distance = distance[node].unwrap_or_default();
+
+                for &next in &neighbours[node] {
+                    if distance[next].is_none() {
+                        distance[next] = Some(node_distance + 1);
+                        queue.push_back(next);
+                    }
+                    if distance[next] == Some(node_distance + 1) {
+                        sigma[next] += sigma[node];
+                        predecessors[next].push(node);
+                    }
+                }
+            }
+
+            let reached = stack.len() - 1;
+            let distance_sum: usize = stack.iter().filter_map(|node| distance[*node]).sum();
+            if let Some(max) = stack.last().and_then(|node| distance[*node]) {
+                diameter = diameter.max(max);
+            }
+            total_length += distance_sum;
+            num_paths += reached;
+
+            if distance_sum > 0 && n > 1 {
+                closeness[source] =
+                    (reached as f64 / distance_sum as f64) * (reached as f64 / (n - 1) as f64);
+            }
+
+            let mut delta = vec![0.0f64; n];
+            while let Some(node) = stack.pop() {
+                for &prev in &predecessors[node] {
+                    delta[prev] += sigma[prev] / sigma[node] * (1.0 + delta[node]);
+                }
+                if node != source {
+                    betweenness[node] += delta[node];
+                }
+            }
+        }
+
+        // Each undirected path was counted from both of its ends.
+        if n > 2 {
+            let scale = 1.0 / ((n - 1) * (n - 2)) as f64;
+            betweenness.iter_mut().for_each(|value| *value *= scale);
+        }
+
+        let average_length = if num_paths == 0 {
+            0.0
+        } else {
+            total_length as f64 / num_paths as f64
+        };
+
+        Self {
+            diameter,
+            average_length,
+            betweenness,
+            closeness,
+        }
+    }
+}
+
+/// Computes eigenvector centrality using power iteration.
+fn eigenvector_centrality(neighbours: &[Vec<usize>]) -> Vec<f64> {
+    let n = neighbours.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let mut scores = vec![1.0 / n as f64; n];
+    for _ in 0..EIGENVECTOR_MAX_ITERATIONS {
+        // Iterating over (A + I) keeps the power method from oscillating on bipartite graphs.
+        let mut next = scores.clone();
+        for (node, list) in neighbours.iter().enumerate() {
+            for &neighbour in list {
+                next[neighbour] += scores[node];
+            }
+        }
+
+        let norm = next.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            return vec![0.0; n];
+        }
+        next.iter_mut().for_each(|v| *v /= norm);
+
+        let change: f64 = next.iter().zip(&scores).map(|(a, b)| (a - b).abs()).sum();
+        scores = next;
+        if change < n as f64 * EIGENVECTOR_TOLERANCE {
+            break;
+        }
+    }
+
+    scores
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn path_graph() -> NodesIndices {
+        // 0 - 1 - 2 - 3
+        vec![vec![1], vec![0, 2], vec![1, 3], vec![2]]
+    }
+
+    #[test]
+    fn should_compute_path_metrics() {
+        let metrics = GraphMetrics::new(&path_graph());
+
+        assert_eq!(metrics.num_edges, 3);
+        assert_eq!(metrics.diameter, 3);
+        assert_eq!(metrics.components.len(), 1);
+        assert_eq!(metrics.degree_distribution.get(&1), Some(&2));
+        assert_eq!(metrics.degree_distribution.get(&2), Some(&2));
+        assert!((metrics.average_path_length - 10.0 / 6.0).abs() < 1e-9);
+        assert!((metrics.betweenness[1] - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(metrics.betweenness[0], 0.0);
+        assert!(metrics.eigenvector[1] > metrics.eigenvector[0]);
+    }
+
+    #[test]
+    fn should_compute_triangle_clustering() {
+        let triangle = vec![vec![1, 2], vec![0, 2], vec![0, 1], vec![]];
+        let metrics = GraphMetrics::new(&triangle);
+
+        assert!((metrics.average_clustering - 0.75).abs() < 1e-9);
+        assert_eq!(metrics.components, vec![vec![0, 1, 2], vec![3]]);
+        assert_eq!(metrics.closeness[3], 0.0);
+        assert!((metrics.closeness[0] - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn should_ignore_out_of_range_indices() {
+        let indices = vec![vec![1, 7], vec![0], vec![usize::MAX]];
+        let metrics = GraphMetrics::new(&indices);
+
+        assert_eq!(metrics.num_edges, 1);
+        assert_eq!(metrics.components, vec![vec![0, 1], vec![2]]);
+    }
+}
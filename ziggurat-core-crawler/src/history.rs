@@ -0,0 +1,249 @@
+//! Archive of successive crawls and trends computed over it.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{address::NodeAddr, migration::migrate, summary::NetworkSummary};
+
+/// Extension of the snapshot files.
+const SNAPSHOT_EXTENSION: &str = "json";
+
+/// A network summary stored in the crawl history.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CrawlSnapshot {
+    /// The time the crawl finished.
+    pub timestamp: SystemTime,
+    /// Summary of the crawl.
+    pub summary: NetworkSummary,
+}
+
+/// Crawl history stored in a local directory, one file per snapshot.
+#[derive(Debug, Clone)]
+pub struct CrawlHistory {
+    dir: PathBuf,
+}
+
+impl CrawlHistory {
+    /// Opens the history stored in the given directory, creating the directory if needed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Appends the summary to the history, timestamped with the current time.
+    pub fn append(&self, summary: &NetworkSummary) -> io::Result<PathBuf> {
+        self.append_at(SystemTime::now(), summary)
+    }
+
+    /// Appends the summary to the history with the given timestamp.
+    ///
+    /// Returns the path of the created snapshot file. Fails with [`io::ErrorKind::AlreadyExists`]
+    /// if a snapshot with the same timestamp is already stored, it is never overwritten.
+    pub fn append_at(
+        &self,
+        timestamp: SystemTime,
+        summary: &NetworkSummary,
+    ) -> io::Result<PathBuf> {
+        let since_epoch = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "timestamp before epoch"))?;
+        let path = self.dir.join(format!(
+            "{:020}-{:09}.{SNAPSHOT_EXTENSION}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos()
+        ));
+
+        let snapshot = CrawlSnapshot {
+            timestamp,
+            summary: summary.clone(),
+        };
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?
+            .write_all(&serde_json::to_vec(&snapshot)?)?;
+
+        Ok(path)
+    }
+
+    /// Loads all snapshots, ordered from the oldest to the newest.
+    pub fn snapshots(&self) -> io::Result<Vec<CrawlSnapshot>> {
+        let mut snapshots: Vec<CrawlSnapshot> = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SNAPSHOT_EXTENSION) {
+                continue;
+            }
+
+            // Summaries of older snapshots are upgraded to the current format.
+            let mut snapshot: Value = serde_json::from_slice(&fs::read(&path)?)?;
+            if let Some(summary) = snapshot.get_mut("summary") {
+                *summary = migrate(summary.take())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            }
+            snapshots.push(serde_json::from_value(snapshot)?);
+        }
+
+        snapshots.sort_by_key(|snapshot| snapshot.timestamp);
+        Ok(snapshots)
+    }
+
+    /// Computes trends over all stored snapshots.
+    pub fn trends(&self) -> io::Result<CrawlTrends> {
+        Ok(CrawlTrends::new(&self.snapshots()?))
+    }
+}
+
+/// Time series computed from a sequence of crawl snapshots.
+///
+/// All series are indexed the same way as `timestamps`.
+#[derive(Default, Clone, Deserialize, Serialize)]
+pub struct CrawlTrends {
+    /// Timestamps of the snapshots.
+    pub timestamps: Vec<SystemTime>,
+    /// Number of nodes that a crawler was able to connect to.
+    pub good_nodes: Vec<usize>,
+    /// Map: User agent -> share of nodes which reported it.
+    pub user_agents: BTreeMap<String, Vec<f64>>,
+    /// Map: Version number -> share of nodes which reported it.
+    pub protocol_versions: BTreeMap<u32, Vec<f64>>,
+    /// Map: Node address -> share of snapshots in which the node was a good node, counted from
+    /// the snapshot in which it first appeared.
+    pub node_uptime: HashMap<NodeAddr, f64>,
+}
+
+impl CrawlTrends {
+    /// Computes trends from snapshots ordered from the oldest to the newest.
+    pub fn new(snapshots: &[CrawlSnapshot]) -> Self {
+        let num_snapshots = snapshots.len();
+        let mut trends = Self {
+            timestamps: snapshots
+                .iter()
+                .map(|snapshot| snapshot.timestamp)
+                .collect(),
+            good_nodes: snapshots
+                .iter()
+                .map(|snapshot| snapshot.summary.num_good_nodes)
+                .collect(),
+            ..Default::default()
+        };
+
+        // Map: Node address -> index of the first snapshot in which the node appeared.
+        let mut first_seen: HashMap<NodeAddr, usize> = HashMap::new();
+        for (index, snapshot) in snapshots.iter().enumerate() {
+            let summary = &snapshot.summary;
+            add_shares(
+                &mut trends.user_agents,
+                &summary.user_agents,
+                index,
+                num_snapshots,
+            );
+            add_shares(
+                &mut trends.protocol_versions,
+                &summary.protocol_versions,
+                index,
+                num_snapshots,
+            );
+
+            for addr in &summary.node_addrs {
+                first_seen.entry(*addr).or_insert(index);
+                *trends.node_uptime.entry(*addr).or_default() += 1.0;
+            }
+        }
+
+        for (addr, uptime) in trends.node_uptime.iter_mut() {
+            *uptime /= (num_snapshots - first_seen[addr]) as f64;
+        }
+
+        trends
+    }
+}
+
+/// Records the share of each item among all counted items of a single snapshot.
+fn add_shares<T: Clone + Ord>(
+    series: &mut BTreeMap<T, Vec<f64>>,
+    counts: &HashMap<T, usize>,
+    index: usize,
+    num_snapshots: usize,
+) {
+    let total: usize = counts.values().sum();
+    if total == 0 {
+        return;
+    }
+
+    for (item, count) in counts {
+        let values = series
+            .entry(item.clone())
+            .or_insert_with(|| vec![0.0; num_snapshots]);
+        values[index] = *count as f64 / total as f64;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::addr;
+
+    fn summary(agents: &[(&str, usize)], addrs: &[u8]) -> NetworkSummary {
+        NetworkSummary {
+            num_good_nodes: addrs.len(),
+            user_agents: agents
+                .iter()
+                .map(|(agent, count)| (agent.to_string(), *count))
+                .collect(),
+            node_addrs: addrs.iter().map(|last| addr(*last)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_compute_trends_over_stored_snapshots() {
+        let dir =
+            std::env::temp_dir().join(format!("ziggurat-crawl-history-{}", std::process::id()));
+        let history = CrawlHistory::open(&dir).unwrap();
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        // Appended out of order on purpose.
+        history
+            .append_at(
+                start + Duration::from_secs(60),
+                &summary(&[("new", 1), ("old", 1)], &[1, 3]),
+            )
+            .unwrap();
+        history
+            .append_at(start, &summary(&[("old", 2)], &[1, 2]))
+            .unwrap();
+        let error = history
+            .append_at(start, &summary(&[("old", 1)], &[1]))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+        let trends = history.trends().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            trends.timestamps,
+            vec![start, start + Duration::from_secs(60)]
+        );
+        assert_eq!(trends.good_nodes, vec![2, 2]);
+        assert_eq!(trends.user_agents["old"], vec![1.0, 0.5]);
+        assert_eq!(trends.user_agents["new"], vec![0.0, 0.5]);
+        assert_eq!(trends.node_uptime[&addr(1)], 1.0);
+        assert_eq!(trends.node_uptime[&addr(2)], 0.5);
+        // Node 3 first appeared in the second snapshot.
+        assert_eq!(trends.node_uptime[&addr(3)], 1.0);
+    }
+}
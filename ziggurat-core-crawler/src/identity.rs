@@ -0,0 +1,264 @@
+//! Grouping of addresses into logical nodes, e.g. dual-stack nodes or nodes listening on
+//! several ports.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address::NodeAddr,
+    graph::adjacency_sets,
+    query::NodeMetadata,
+    summary::{NetworkSummary, NetworkType},
+};
+
+/// Groups addresses which belong to the same physical node.
+///
+/// Each group is identified by its lowest address, so identities don't depend on the order in
+/// which evidence was added.
+#[derive(Default, Clone, Debug)]
+pub struct NodeIdentities {
+    /// Map: Address -> address of its parent in the union-find forest.
+    parents: HashMap<NodeAddr, NodeAddr>,
+    /// Map: Evidence -> first address it was seen at.
+    evidence: HashMap<String, NodeAddr>,
+}
+
+impl NodeIdentities {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records evidence seen at the address, such as a node id or a nonce from the handshake.
+    ///
+    /// Addresses sharing any evidence are grouped into one logical node.
+    pub fn add_evidence<E: Into<String>>(&mut self, addr: NodeAddr, evidence: E) {
+        let evidence = evidence.into();
+        match self.evidence.get(&evidence) {
+            Some(&first) => self.link(first, addr),
+            None => {
+                self.insert(addr);
+                self.evidence.insert(evidence, addr);
+            }
+        }
+    }
+
+    /// Groups the two addresses into one logical node.
+    pub fn link(&mut self, a: NodeAddr, b: NodeAddr) {
+        let (a, b) = (self.insert(a), self.insert(b));
+        if a != b {
+            // Keep the lowest address as the root.
+            self.parents.insert(a.max(b), a.min(b));
+        }
+    }
+
+    /// Returns the address identifying the logical node the address belongs to.
+    pub fn identity(&self, addr: &NodeAddr) -> NodeAddr {
+        let mut current = *addr;
+        while let Some(parent) = self.parents.get(&current) {
+            if *parent == current {
+                break;
+            }
+            current = *parent;
+        }
+        current
+    }
+
+    /// Returns the groups of more than one address, each sorted with its identity first.
+    pub fn groups(&self) -> Vec<Vec<NodeAddr>> {
+        let mut groups: BTreeMap<NodeAddr, Vec<NodeAddr>> = BTreeMap::new();
+        for addr in self.parents.keys() {
+            groups.entry(self.identity(addr)).or_default().push(*addr);
+        }
+
+        groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort_unstable();
+                group
+            })
+            .collect()
+    }
+
+    /// Adds the address if needed and returns its root, compressing the path to it.
+    fn insert(&mut self, addr: NodeAddr) -> NodeAddr {
+        self.parents.entry(addr).or_insert(addr);
+        let root = self.identity(&addr);
+
+        let mut current = addr;
+        while current != root {
+            let parent = self.parents[&current];
+            self.parents.insert(current, root);
+            current = parent;
+        }
+
+        root
+    }
+}
+
+/// Network summary computed over logical nodes rather than addresses.
+#[derive(Default, Clone, Deserialize, Serialize)]
+pub struct LogicalSummary {
+    /// The summary, each logical node is represented by its lowest address.
+    pub summary: NetworkSummary,
+    /// All addresses of each logical node, sorted.
+    /// Indexes correspond to `summary.node_addrs`.
+    pub members: Vec<Vec<NodeAddr>>,
+}
+
+impl NetworkSummary {
+    /// Collapses addresses of the same physical node into logical nodes.
+    ///
+    /// Connections are unioned, connections between addresses of the same node are dropped,
+    /// and each logical node is counted once in the versions and user agents, which are taken
+    /// from the first of its addresses for which `metadata` or the node records have them.
+    /// Node records of the addresses are merged into one record per logical node. Connections
+    /// of nodes without an address are skipped.
+    pub fn logical<M: NodeMetadata + ?Sized>(
+        &self,
+        identities: &NodeIdentities,
+        metadata: &M,
+    ) -> LogicalSummary {
+        let mut groups: BTreeMap<NodeAddr, Vec<usize>> = BTreeMap::new();
+        for (index, addr) in self.node_addrs.iter().enumerate() {
+            groups
+                .entry(identities.identity(addr))
+                .or_default()
+                .push(index);
+        }
+
+        let mut logical_indices = vec![0; self.node_addrs.len()];
+        for (position, indices) in groups.values().enumerate() {
+            for index in indices {
+                logical_indices[*index] = position;
+            }
+        }
+
+        let adjacency = adjacency_sets(&self.nodes_indices);
+        let num_connections = adjacency.iter().map(|set| set.len()).sum::<usize>() / 2;
+        let mut connections = BTreeSet::new();
+        for (node, neighbours) in adjacency.iter().enumerate().take(self.node_addrs.len()) {
+            for neighbour in neighbours {
+                let Some(&b) = logical_indices.get(*neighbour) else {
+                    continue;
+                };
+                let a = logical_indices[node];
+                if a != b {
+                    connections.insert((a.min(b), a.max(b)));
+                }
+            }
+        }
+
+        let mut summary = NetworkSummary {
+            num_known_nodes: self
+                .num_known_nodes
+                .saturating_sub(self.node_addrs.len() - groups.len())
+                .max(groups.len()),
+            num_good_nodes: groups.len(),
+            num_known_connections: self
+                .num_known_connections
+                .saturating_sub(num_connections.saturating_sub(connections.len()))
+                .max(connections.len()),
+            crawler_runtime: self.crawler_runtime,
+            nodes_indices: vec![Vec::new(); groups.len()],
+            ..Default::default()
+        };
+        for (a, b) in &connections {
+            summary.nodes_indices[*a].push(*b);
+            summary.nodes_indices[*b].push(*a);
+        }
+
+        let mut members = Vec::with_capacity(groups.len());
+        for mut indices in groups.into_values() {
+            indices.sort_unstable_by_key(|index| self.node_addrs[*index]);
+            let addrs: Vec<NodeAddr> = indices
+                .iter()
+                .map(|index| self.node_addrs[*index])
+                .collect();
+            let versions: Vec<(Option<u32>, Option<&str>)> = indices
+                .iter()
+                .map(|index| self.node_version(*index, metadata))
+                .collect();
+
+            if let Some(version) = versions.iter().find_map(|(version, _)| *version) {
+                summary.num_versions += 1;
+                *summary.protocol_versions.entry(version).or_default() += 1;
+            }
+            if let Some(agent) = versions.iter().find_map(|(_, agent)| *agent) {
+                *summary.user_agents.entry(agent.to_owned()).or_default() += 1;
+            }
+            if self.has_node_records() {
+                let mut record = self.node_records[indices[0]].clone();
+                for index in &indices[1..] {
+                    record.merge(&self.node_records[*index]);
+                }
+                summary.node_records.push(record);
+            }
+
+            summary.node_addrs.push(addrs[0]);
+            summary.node_network_types.push(
+                indices
+                    .iter()
+                    .filter_map(|index| self.node_network_types.get(*index))
+                    .find(|network_type| **network_type != NetworkType::Unknown)
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+            members.push(addrs);
+        }
+
+        LogicalSummary { summary, members }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{network::KnownNetwork, testing::addr};
+
+    #[test]
+    fn should_collapse_dual_stack_nodes() {
+        let v4 = addr(4);
+        let v6: NodeAddr = "[2001:db8::4]:8233".parse().unwrap();
+        let other_port: NodeAddr = "1.2.3.4:18233".parse().unwrap();
+        let peer = addr(5);
+
+        let mut network = KnownNetwork::new();
+        for addr in [v4, v6, other_port, peer] {
+            let node = network.node_mut(addr);
+            node.handshake_successful = true;
+            node.protocol_version = Some(170100);
+        }
+        network.touch_connection(v4, peer);
+        network.touch_connection(v6, peer);
+        network.touch_connection(v4, v6);
+        let summary = network.summary();
+
+        let mut identities = NodeIdentities::new();
+        identities.add_evidence(v6, "nonce:42");
+        identities.add_evidence(peer, "nonce:7");
+        identities.add_evidence(v4, "nonce:42");
+        identities.link(other_port, v6);
+
+        assert_eq!(identities.identity(&v6), v4);
+        assert_eq!(identities.groups(), vec![vec![v4, other_port, v6]]);
+
+        let logical = summary.logical(&identities, &network);
+        assert_eq!(logical.summary.node_addrs, vec![v4, peer]);
+        assert_eq!(logical.members[0], vec![v4, other_port, v6]);
+        assert_eq!(logical.summary.nodes_indices, vec![vec![1], vec![0]]);
+        assert_eq!(logical.summary.protocol_versions[&170100], 2);
+        assert_eq!(logical.summary.num_known_nodes, 2);
+        assert_eq!(logical.summary.num_known_connections, 1);
+        assert_eq!(logical.summary.graph_metrics().num_nodes, 2);
+        assert_eq!(logical.summary.node_records[0].addr, v4);
+        assert!(logical.summary.validate().is_empty());
+
+        // Indices of a node without an address.
+        let mut summary = summary;
+        summary.nodes_indices[0].push(4);
+        summary.nodes_indices.push(vec![0]);
+        let logical = summary.logical(&identities, &network);
+        assert_eq!(logical.summary.nodes_indices, vec![vec![1], vec![0]]);
+    }
+}
@@ -0,0 +1,257 @@
+//! Force-directed layout of the crawled network topology, used by visualisers.
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "geoip")]
+use ziggurat_core_geoip::coordinates::Coordinates;
+
+use crate::{
+    graph::{adjacency_sets, sorted_neighbours},
+    summary::{NetworkSummary, NodesIndices},
+};
+
+/// Distance below which two nodes are considered to overlap.
+const MIN_DISTANCE: f64 = 1e-9;
+/// Initial maximum displacement of a node per iteration, relative to the layout size.
+const INITIAL_TEMPERATURE: f64 = 0.1;
+/// Maximum random offset added to seeded positions, so that nodes sharing a location separate.
+#[cfg(feature = "geoip")]
+const SEED_JITTER: f64 = 1e-3;
+
+/// Parameters of the layout computation.
+///
+/// Non-exhaustive, as seeding from coordinates is only available with the `geoip` feature, so
+/// it's constructed from [`LayoutConfig::default`] and adjusted field by field.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct LayoutConfig {
+    /// Number of dimensions, either 2 or 3.
+    pub dimensions: usize,
+    /// Number of iterations of the simulation.
+    pub iterations: usize,
+    /// Seed of the random initial positions.
+    pub seed: u64,
+    /// Coordinates used to seed initial positions, longitude mapped to `x` and latitude to `y`.
+    /// Indexes correspond to `node_addrs`, nodes without coordinates start at random positions.
+    #[cfg(feature = "geoip")]
+    pub coordinates: Vec<Option<Coordinates>>,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            dimensions: 2,
+            iterations: 100,
+            seed: 0,
+            #[cfg(feature = "geoip")]
+            coordinates: Vec::new(),
+        }
+    }
+}
+
+/// Network summary annotated with layout positions of its nodes.
+#[derive(Default, Clone, Deserialize, Serialize)]
+pub struct LayoutSummary {
+    /// The annotated network summary.
+    pub summary: NetworkSummary,
+    /// Position of each node, with as many components as there are dimensions.
+    /// Indexes correspond to `node_addrs`.
+    pub positions: Vec<Vec<f64>>,
+}
+
+impl LayoutSummary {
+    /// Lays out the nodes of the summary.
+    pub fn new(summary: &NetworkSummary, config: &LayoutConfig) -> Result<Self, String> {
+        Ok(Self {
+            summary: summary.clone(),
+            positions: layout(&summary.nodes_indices, config)?,
+        })
+    }
+}
+
+impl NetworkSummary {
+    /// Computes a force-directed layout of the crawled network.
+    pub fn layout(&self, config: &LayoutConfig) -> Result<LayoutSummary, String> {
+        LayoutSummary::new(self, config)
+    }
+}
+
+/// Positions the nodes of the graph using the Fruchterman-Reingold algorithm within a unit
+/// square or cube centered at the origin.
+///
+/// As in the grid variant of the algorithm, nodes only repel the nodes closer than twice the
+/// optimal distance, which are found through a grid of that cell size. An iteration thus takes
+/// roughly linear time unless many nodes are crammed together.
+pub fn layout(indices: &NodesIndices, config: &LayoutConfig) -> Result<Vec<Vec<f64>>, String> {
+    let dimensions = config.dimensions;
+    if !(2..=3).contains(&dimensions) {
+        return Err(format!("unsupported number of dimensions: {dimensions}"));
+    }
+
+    let neighbours = sorted_neighbours(&adjacency_sets(indices));
+    let n = neighbours.len();
+    let mut rng = StdRng::seed_from_u64(config.seed);
+
+    let mut positions: Vec<[f64; 3]> = (0..n)
+        .map(|_| {
+            let mut position = [0.0; 3];
+            for component in position.iter_mut().take(dimensions) {
+                *component = rng.gen_range(-0.5..0.5);
+            }
+            position
+        })
+        .collect();
+    #[cfg(feature = "geoip")]
+    for (position, coordinates) in positions.iter_mut().zip(&config.coordinates) {
+        if let Some(coordinates) = coordinates {
+            let jitter = [(); 2].map(|_| rng.gen_range(-SEED_JITTER..SEED_JITTER));
+            position[0] = (coordinates.longitude / 360.0 + jitter[0]).clamp(-0.5, 0.5);
+            position[1] = (coordinates.latitude / 180.0 + jitter[1]).clamp(-0.5, 0.5);
+        }
+    }
+
+    // Optimal distance between nodes, given the unit volume.
+    let k = (1.0 / n.max(1) as f64).powf(1.0 / dimensions as f64);
+    let mut temperature = INITIAL_TEMPERATURE;
+    let cooling = INITIAL_TEMPERATURE / config.iterations.max(1) as f64;
+
+    for _ in 0..config.iterations {
+        let mut displacements = vec![[0.0; 3]; n];
+
+        let cell_size = 2.0 * k;
+        let cell =
+            |position: &[f64; 3]| position.map(|component| (component / cell_size).floor() as i64);
+        let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        for (node, position) in positions.iter().enumerate() {
+            grid.entry(cell(position)).or_default().push(node);
+        }
+
+        let offsets: Vec<[i64; 3]> = (0..3_i64.pow(dimensions as u32))
+            .map(|index| {
+                let mut offset = [0; 3];
+                for (d, component) in offset.iter_mut().take(dimensions).enumerate() {
+                    *component = index / 3_i64.pow(d as u32) % 3 - 1;
+                }
+                offset
+            })
+            .collect();
+
+        for (i, position) in positions.iter().enumerate() {
+            let [x, y, z] = cell(position);
+            for [dx, dy, dz] in &offsets {
+                for &j in grid.get(&[x + dx, y + dy, z + dz]).into_iter().flatten() {
+                    let (delta, distance) = difference(position, &positions[j]);
+                    if j == i || distance >= cell_size {
+                        continue;
+                    }
+                    let force = k * k / distance;
+                    for d in 0..3 {
+                        displacements[i][d] += delta[d] / distance * force;
+                    }
+                }
+            }
+        }
+
+        for (i, peers) in neighbours.iter().enumerate() {
+            for &j in peers.iter().filter(|j| **j > i) {
+                let (delta, distance) = difference(&positions[i], &positions[j]);
+                let force = distance * distance / k;
+                for d in 0..3 {
+                    displacements[i][d] -= delta[d] / distance * force;
+                    displacements[j][d] += delta[d] / distance * force;
+                }
+            }
+        }
+
+        for (position, displacement) in positions.iter_mut().zip(&displacements) {
+            let length = norm(displacement).max(MIN_DISTANCE);
+            let step = length.min(temperature);
+            for d in 0..dimensions {
+                position[d] = (position[d] + displacement[d] / length * step).clamp(-0.5, 0.5);
+            }
+        }
+
+        temperature -= cooling;
+    }
+
+    Ok(positions
+        .into_iter()
+        .map(|position| position[..dimensions].to_vec())
+        .collect())
+}
+
+/// Returns the vector from `b` to `a` and its length, never below the minimum distance.
+fn difference(a: &[f64; 3], b: &[f64; 3]) -> ([f64; 3], f64) {
+    let delta = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    (delta, norm(&delta).max(MIN_DISTANCE))
+}
+
+fn norm(vector: &[f64; 3]) -> f64 {
+    vector
+        .iter()
+        .map(|component| component * component)
+        .sum::<f64>()
+        .sqrt()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn distance(a: &[f64], b: &[f64]) -> f64 {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    #[test]
+    fn should_lay_out_deterministically() {
+        // Two disconnected triangles.
+        let indices = vec![vec![1, 2], vec![2], vec![], vec![4, 5], vec![5], vec![]];
+        let config = LayoutConfig {
+            dimensions: 3,
+            ..Default::default()
+        };
+
+        let positions = layout(&indices, &config).unwrap();
+
+        assert_eq!(positions, layout(&indices, &config).unwrap());
+        assert!(positions.iter().all(|position| position.len() == 3));
+        assert!(positions
+            .iter()
+            .flatten()
+            .all(|component| (-0.5..=0.5).contains(component)));
+        let within = distance(&positions[0], &positions[1]);
+        let across = distance(&positions[0], &positions[3]);
+        assert!(within < across);
+
+        let config = LayoutConfig {
+            dimensions: 4,
+            ..Default::default()
+        };
+        assert!(layout(&indices, &config).is_err());
+    }
+
+    #[cfg(feature = "geoip")]
+    #[test]
+    fn should_seed_positions_from_coordinates() {
+        let config = LayoutConfig {
+            iterations: 0,
+            coordinates: vec![
+                Some(Coordinates::new(40.0, -100.0)),
+                None,
+                Some(Coordinates::new(-30.0, 100.0)),
+            ],
+            ..Default::default()
+        };
+
+        let positions = layout(&vec![vec![1], vec![2], vec![]], &config).unwrap();
+
+        assert!(positions[0][0] < positions[2][0]);
+        assert!(positions[0][1] > positions[2][1]);
+        assert!((positions[2][0] - 100.0 / 360.0).abs() <= SEED_JITTER);
+    }
+}
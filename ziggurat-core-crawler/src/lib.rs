@@ -1,3 +1,29 @@
 //! Crawler specific data types and methods.
+pub mod address;
+pub mod anonymise;
+pub mod binary;
+pub mod community;
 pub mod connection;
+pub mod diversity;
+pub mod eclipse;
+#[cfg(feature = "crawler")]
+pub mod engine;
+pub mod export;
+#[cfg(feature = "geoip")]
+pub mod geo;
+pub mod gossip;
+pub mod graph;
+pub mod history;
+pub mod identity;
+pub mod layout;
+pub mod merge;
+pub mod migration;
+pub mod network;
+pub mod query;
+pub mod record;
+pub mod resilience;
 pub mod summary;
+#[cfg(test)]
+mod testing;
+pub mod user_agent;
+pub mod validation;
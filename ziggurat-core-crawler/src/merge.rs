@@ -0,0 +1,295 @@
+//! Merging of summaries produced by crawlers running from different vantage points.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    hash::Hash,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address::NodeAddr,
+    record::NodeRecord,
+    summary::{NetworkSummary, NetworkType},
+};
+
+/// Network summary merged from several vantage points.
+///
+/// `num_known_nodes` and `num_known_connections` of the merged summary are lower bounds, the
+/// largest of the merged counts, as nodes known but not connected to can't be unified.
+#[derive(Default, Clone, Deserialize, Serialize)]
+pub struct MergedSummary {
+    /// The merged summary.
+    pub summary: NetworkSummary,
+    /// Whether `protocol_versions`, `num_versions` and `user_agents` of the merged summary are
+    /// estimates, which is the case when some of the merged summaries have no node records.
+    pub approximate: bool,
+    /// Names of the vantage points, in the order they were merged.
+    pub vantage_points: Vec<String>,
+    /// Indexes into `vantage_points` of the crawlers which observed each node.
+    /// Indexes correspond to `summary.node_addrs`.
+    pub observed_by: Vec<Vec<usize>>,
+}
+
+impl MergedSummary {
+    /// Merges summaries from named vantage points.
+    ///
+    /// Nodes are unified by their address and connections are unioned. When every summary
+    /// carries node records, the records of each node are merged and the counts derived from
+    /// them. Otherwise only the aggregated `protocol_versions` and `user_agents` are available,
+    /// so the counts of each summary are scaled by a single weight: the mean of `1/k` over its
+    /// nodes, `k` being the number of vantage points which observed the node. Every version
+    /// and user agent of a summary gets the same discount, wherever its nodes were observed,
+    /// so such counts are estimates, flagged by `approximate`, unless every node is observed
+    /// by a single vantage point.
+    pub fn new<'a, I: IntoIterator<Item = (&'a str, &'a NetworkSummary)>>(summaries: I) -> Self {
+        let summaries: Vec<(&str, &NetworkSummary)> = summaries.into_iter().collect();
+
+        // Map: Address -> (vantage points which observed it, network type).
+        let mut nodes: BTreeMap<NodeAddr, (Vec<usize>, NetworkType)> = BTreeMap::new();
+        for (vantage_point, (_, summary)) in summaries.iter().enumerate() {
+            for (index, addr) in summary.node_addrs.iter().enumerate() {
+                let (observers, network_type) = nodes.entry(*addr).or_default();
+                if observers.last() != Some(&vantage_point) {
+                    observers.push(vantage_point);
+                }
+                if *network_type == NetworkType::Unknown {
+                    if let Some(known) = summary.node_network_types.get(index) {
+                        *network_type = known.clone();
+                    }
+                }
+            }
+        }
+
+        let positions: HashMap<NodeAddr, usize> = nodes
+            .keys()
+            .enumerate()
+            .map(|(position, addr)| (*addr, position))
+            .collect();
+
+        let mut connections = BTreeSet::new();
+        let mut protocol_versions = HashMap::new();
+        let mut user_agents = HashMap::new();
+        let mut merged = NetworkSummary::default();
+
+        for (_, summary) in &summaries {
+            for (node, neighbours) in summary.nodes_indices.iter().enumerate() {
+                for neighbour in neighbours {
+                    if let (Some(a), Some(b)) = (
+                        summary.node_addrs.get(node).map(|addr| positions[addr]),
+                        summary
+                            .node_addrs
+                            .get(*neighbour)
+                            .map(|addr| positions[addr]),
+                    ) {
+                        if a != b {
+                            connections.insert((a.min(b), a.max(b)));
+                        }
+                    }
+                }
+            }
+
+            // Share of the summary's nodes which is not accounted for by other vantage points.
+            let weight = if summary.node_addrs.is_empty() {
+                1.0
+            } else {
+                let unique: BTreeSet<&NodeAddr> = summary.node_addrs.iter().collect();
+                unique
+                    .iter()
+                    .map(|addr| 1.0 / nodes[*addr].0.len() as f64)
+                    .sum::<f64>()
+                    / unique.len() as f64
+            };
+            add_weighted(&mut protocol_versions, &summary.protocol_versions, weight);
+            add_weighted(&mut user_agents, &summary.user_agents, weight);
+
+            merged.num_known_nodes = merged.num_known_nodes.max(summary.num_known_nodes);
+            merged.num_known_connections = merged
+                .num_known_connections
+                .max(summary.num_known_connections);
+            merged.crawler_runtime = merged.crawler_runtime.max(summary.crawler_runtime);
+        }
+
+        merged.nodes_indices = vec![Vec::new(); nodes.len()];
+        for (a, b) in &connections {
+            merged.nodes_indices[*a].push(*b);
+            merged.nodes_indices[*b].push(*a);
+        }
+
+        merged.num_good_nodes = nodes.len();
+        merged.num_known_nodes = merged.num_known_nodes.max(nodes.len());
+        merged.num_known_connections = merged.num_known_connections.max(connections.len());
+
+        // Records are only usable if they line up with the addresses the graph was built from.
+        let has_records = summaries.iter().all(|(_, summary)| {
+            summary.node_addrs.is_empty()
+                || (summary.has_node_records()
+                    && summary
+                        .node_addrs
+                        .iter()
+                        .zip(&summary.node_records)
+                        .all(|(addr, record)| record.addr == *addr))
+        });
+        if has_records {
+            let mut records: BTreeMap<NodeAddr, NodeRecord> = BTreeMap::new();
+            for (_, summary) in &summaries {
+                for record in &summary.node_records {
+                    records
+                        .entry(record.addr)
+                        .and_modify(|merged| merged.merge(record))
+                        .or_insert_with(|| record.clone());
+                }
+            }
+
+            protocol_versions.clear();
+            user_agents.clear();
+            for (addr, (_, network_type)) in &nodes {
+                let mut record = records
+                    .remove(addr)
+                    .unwrap_or_else(|| NodeRecord::new(*addr));
+                record.network_type = network_type.clone();
+                if let Some(version) = record.protocol_version {
+                    *protocol_versions.entry(version).or_default() += 1.0;
+                }
+                if let Some(user_agent) = &record.user_agent {
+                    *user_agents.entry(user_agent.clone()).or_default() += 1.0;
+                }
+                merged.node_records.push(record);
+            }
+        }
+
+        merged.protocol_versions = round_counts(protocol_versions);
+        merged.user_agents = round_counts(user_agents);
+        merged.num_versions = merged.protocol_versions.values().sum();
+
+        // Nodes observed by a single vantage point are counted exactly even without records.
+        let approximate = !has_records && nodes.values().any(|(observers, _)| observers.len() > 1);
+
+        let mut observed_by = Vec::with_capacity(nodes.len());
+        for (addr, (observers, network_type)) in nodes {
+            merged.node_addrs.push(addr);
+            merged.node_network_types.push(network_type);
+            observed_by.push(observers);
+        }
+
+        Self {
+            summary: merged,
+            approximate,
+            vantage_points: summaries.iter().map(|(name, _)| name.to_string()).collect(),
+            observed_by,
+        }
+    }
+
+    /// Returns the names of the vantage points which observed the node.
+    pub fn observers(&self, addr: &NodeAddr) -> Vec<&str> {
+        self.summary
+            .node_addrs
+            .iter()
+            .position(|node| node == addr)
+            .map(|index| {
+                self.observed_by[index]
+                    .iter()
+                    .map(|vantage_point| self.vantage_points[*vantage_point].as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn add_weighted<T: Clone + Eq + Hash>(
+    totals: &mut HashMap<T, f64>,
+    counts: &HashMap<T, usize>,
+    weight: f64,
+) {
+    for (item, count) in counts {
+        *totals.entry(item.clone()).or_default() += *count as f64 * weight;
+    }
+}
+
+fn round_counts<T: Eq + Hash>(totals: HashMap<T, f64>) -> HashMap<T, usize> {
+    totals
+        .into_iter()
+        .map(|(item, total)| (item, total.round() as usize))
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::addr;
+
+    #[test]
+    fn should_merge_vantage_points() {
+        // Both crawlers see nodes 1 and 2, numbered differently.
+        let europe = NetworkSummary {
+            num_known_nodes: 3,
+            num_good_nodes: 3,
+            protocol_versions: HashMap::from([(170100, 3)]),
+            node_addrs: vec![addr(1), addr(2), addr(3)],
+            node_network_types: vec![NetworkType::Zcash; 3],
+            nodes_indices: vec![vec![1], vec![0, 2], vec![1]],
+            ..Default::default()
+        };
+        let asia = NetworkSummary {
+            num_known_nodes: 3,
+            num_good_nodes: 3,
+            protocol_versions: HashMap::from([(170100, 3)]),
+            node_addrs: vec![addr(4), addr(2), addr(1)],
+            node_network_types: vec![NetworkType::Zcash; 3],
+            nodes_indices: vec![vec![2], vec![2], vec![0, 1]],
+            ..Default::default()
+        };
+
+        let merged = MergedSummary::new([("europe", &europe), ("asia", &asia)]);
+        let summary = &merged.summary;
+
+        assert_eq!(summary.node_addrs, vec![addr(1), addr(2), addr(3), addr(4)]);
+        assert_eq!(
+            summary.nodes_indices,
+            vec![vec![1, 3], vec![0, 2], vec![1], vec![0]]
+        );
+        assert_eq!(summary.num_good_nodes, 4);
+        assert_eq!(summary.protocol_versions[&170100], 4);
+        assert_eq!(summary.num_versions, 4);
+        assert!(merged.approximate);
+        assert_eq!(merged.observers(&addr(1)), vec!["europe", "asia"]);
+        assert_eq!(merged.observers(&addr(4)), vec!["asia"]);
+        assert!(summary.validate().is_empty());
+    }
+
+    #[test]
+    fn should_merge_node_records() {
+        let record = |last: u8, version: u32| NodeRecord {
+            protocol_version: Some(version),
+            ..NodeRecord::new(addr(last))
+        };
+        let summary = |records: Vec<NodeRecord>| {
+            let mut summary = NetworkSummary {
+                nodes_indices: vec![Vec::new(); records.len()],
+                node_records: records,
+                ..Default::default()
+            };
+            summary.derive_from_records();
+            summary
+        };
+
+        let europe = summary(vec![record(1, 1), record(2, 2)]);
+        let mut asia = summary(vec![record(2, 2), record(3, 1)]);
+        asia.node_records[0].user_agent = Some("/MagicBean:5.4.2/".to_owned());
+
+        let merged = MergedSummary::new([("europe", &europe), ("asia", &asia)]);
+        assert!(!merged.approximate);
+        let merged = merged.summary;
+
+        assert_eq!(merged.node_records.len(), 3);
+        assert_eq!(merged.protocol_versions, HashMap::from([(1, 2), (2, 1)]));
+        assert_eq!(merged.user_agents["/MagicBean:5.4.2/"], 1);
+        assert!(merged.validate().is_empty());
+
+        // Records which don't line up with the addresses are ignored.
+        asia.node_records.swap(0, 1);
+        let merged = MergedSummary::new([("europe", &europe), ("asia", &asia)]);
+        assert!(merged.approximate);
+        assert!(merged.summary.node_records.is_empty());
+    }
+}
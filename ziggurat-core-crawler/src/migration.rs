@@ -0,0 +1,151 @@
+//! Upgrades of network summaries persisted in older formats.
+//!
+//! Summaries persisted before [`SCHEMA_VERSION`] was introduced carry no version, it's inferred
+//! from the fields they have:
+//!
+//! 1. The original format, without `node_network_types`.
+//! 2. Adds `node_network_types`.
+//! 3. Adds `node_records` and the explicit `schema_version`.
+//!
+//! Fields added since the original format have serde defaults, so older summaries also
+//! deserialize directly, e.g. when embedded in other types, but without being upgraded.
+use std::io::Read;
+
+use serde_json::{Map, Value};
+
+use crate::summary::{NetworkSummary, NetworkType, SCHEMA_VERSION};
+
+/// Upgrades a summary from the given version to the next one.
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// Migrations indexed by the version they upgrade from, minus one.
+const MIGRATIONS: [Migration; 2] = [add_network_types, add_node_records];
+
+/// Returns the format version of a serialized summary, either explicit or inferred.
+pub fn schema_version(summary: &Value) -> Result<u32, String> {
+    let fields = summary
+        .as_object()
+        .ok_or("network summary must be a JSON object")?;
+
+    match fields.get("schema_version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| format!("invalid schema version: {version}")),
+        None if fields.contains_key("node_network_types") => Ok(2),
+        None => Ok(1),
+    }
+}
+
+/// Upgrades a serialized summary of any known version to the current format.
+pub fn migrate(mut summary: Value) -> Result<Value, String> {
+    let version = schema_version(&summary)?;
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(format!("unsupported schema version: {version}"));
+    }
+
+    // Checked by `schema_version`.
+    let fields = summary.as_object_mut().unwrap();
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(fields)?;
+    }
+    fields.insert("schema_version".to_owned(), SCHEMA_VERSION.into());
+
+    Ok(summary)
+}
+
+impl NetworkSummary {
+    /// Deserializes a summary of any known format version, upgrading it to the current one.
+    pub fn from_json_value(summary: Value) -> Result<Self, String> {
+        serde_json::from_value(migrate(summary)?).map_err(|e| e.to_string())
+    }
+
+    /// Reads a JSON summary of any known format version, upgrading it to the current one.
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Self, String> {
+        let summary: Value = serde_json::from_reader(reader).map_err(|e| e.to_string())?;
+        Self::from_json_value(summary)
+    }
+}
+
+/// Version 2 added network types, which weren't known before.
+fn add_network_types(fields: &mut Map<String, Value>) -> Result<(), String> {
+    let num_nodes = fields
+        .get("node_addrs")
+        .and_then(Value::as_array)
+        .ok_or("missing node_addrs")?
+        .len();
+    let unknown = serde_json::to_value(NetworkType::Unknown).map_err(|e| e.to_string())?;
+    fields.insert(
+        "node_network_types".to_owned(),
+        Value::Array(vec![unknown; num_nodes]),
+    );
+
+    Ok(())
+}
+
+/// Version 3 added node records, which can't be recovered from the aggregates.
+fn add_node_records(fields: &mut Map<String, Value>) -> Result<(), String> {
+    fields
+        .entry("node_records")
+        .or_insert_with(|| Value::Array(Vec::new()));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::layout::LayoutSummary;
+
+    /// Summaries persisted by each version, the second one written by the original crate.
+    const FIXTURES: [(u32, &str); 3] = [
+        (1, include_str!("../tests/fixtures/summary_v1.json")),
+        (2, include_str!("../tests/fixtures/summary_v2.json")),
+        (3, include_str!("../tests/fixtures/summary_v3.json")),
+    ];
+
+    #[test]
+    fn should_upgrade_historical_formats() {
+        for (version, fixture) in FIXTURES {
+            let value: Value = serde_json::from_str(fixture).unwrap();
+            assert_eq!(schema_version(&value), Ok(version));
+
+            let summary = NetworkSummary::from_json_reader(fixture.as_bytes()).unwrap();
+
+            assert_eq!(summary.schema_version, SCHEMA_VERSION);
+            assert_eq!(summary.node_addrs.len(), 2);
+            assert_eq!(summary.protocol_versions[&170100], 2);
+            assert!(summary.validate().is_empty(), "version {version}");
+            let expected = if version == 1 {
+                NetworkType::Unknown
+            } else {
+                NetworkType::Zcash
+            };
+            assert_eq!(summary.node_network_types[0], expected);
+        }
+
+        // Legacy summaries embedded in other types deserialize as well.
+        let legacy: Value = serde_json::from_str(FIXTURES[0].1).unwrap();
+        let layout: LayoutSummary = serde_json::from_value(serde_json::json!({
+            "summary": legacy,
+            "positions": [[0.0, 0.0], [1.0, 1.0]],
+        }))
+        .unwrap();
+        assert_eq!(layout.summary.schema_version, 1);
+        assert!(layout.summary.node_records.is_empty());
+        assert!(layout.summary.node_network_types.is_empty());
+
+        let current = serde_json::to_value(NetworkSummary::default()).unwrap();
+        assert_eq!(schema_version(&current), Ok(SCHEMA_VERSION));
+        assert!(NetworkSummary::from_json_value(current).is_ok());
+    }
+
+    #[test]
+    fn should_reject_unknown_versions() {
+        let mut summary = serde_json::to_value(NetworkSummary::default()).unwrap();
+        summary["schema_version"] = (SCHEMA_VERSION + 1).into();
+
+        assert!(migrate(summary).is_err());
+        assert!(migrate(Value::Array(Vec::new())).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    address::NodeAddr,
+    connection::KnownConnection,
+    record::NodeRecord,
+    summary::{NetworkSummary, NetworkType, NodesIndices},
+};
+
+/// Information about a node known to the crawler.
+#[derive(Debug, Default, Clone)]
+pub struct KnownNode {
+    /// The timestamp of the first time the node was discovered.
+    ///
+    /// Wall-clock time is used, as for connections, so that it can be recorded in summaries.
+    pub first_seen: Option<SystemTime>,
+    /// The timestamp of the last time the crawler connected to the node.
+    pub last_connected: Option<SystemTime>,
+    /// Whether the last handshake with the node was successful.
+    pub handshake_successful: bool,
+    /// Number of failed attempts since the last complete crawl of the node, including the ones
+    /// where only requesting its peers failed.
+    pub connection_failures: u8,
+    /// Protocol version reported by the node.
+    pub protocol_version: Option<u32>,
+    /// Software version reported by the node.
+    pub user_agent: Option<String>,
+    /// Network the node belongs to.
+    pub network_type: NetworkType,
+    /// Service flags advertised by the node.
+    pub services: Option<u64>,
+    /// Block height advertised by the node during the handshake.
+    pub start_height: Option<u64>,
+    /// Time it took to complete the last successful handshake.
+    pub handshake_latency: Option<Duration>,
+}
+
+/// Known state of a crawled network: its nodes and the connections between them.
+#[derive(Debug, Clone)]
+pub struct KnownNetwork {
+    /// The timestamp of the crawl start.
+    started: Instant,
+    connections: HashSet<KnownConnection>,
+    nodes: HashMap<NodeAddr, KnownNode>,
+}
+
+impl Default for KnownNetwork {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            connections: Default::default(),
+            nodes: Default::default(),
+        }
+    }
+}
+
+impl KnownNetwork {
+    /// Creates an empty network, the crawler's runtime is measured from this point.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns all known connections.
+    pub fn connections(&self) -> &HashSet<KnownConnection> {
+        &self.connections
+    }
+
+    /// Returns all known nodes.
+    pub fn nodes(&self) -> &HashMap<NodeAddr, KnownNode> {
+        &self.nodes
+    }
+
+    /// Returns the node with the given address.
+    pub fn node(&self, addr: &NodeAddr) -> Option<&KnownNode> {
+        self.nodes.get(addr)
+    }
+
+    /// Returns the node with the given address, adding it first if it's not known yet.
+    pub fn node_mut(&mut self, addr: NodeAddr) -> &mut KnownNode {
+        self.nodes.entry(addr).or_insert_with(|| KnownNode {
+            first_seen: Some(SystemTime::now()),
+            ..Default::default()
+        })
+    }
+
+    /// Adds the connection or refreshes its `last_seen` timestamp if it's already known.
+    ///
+    /// Both sides of the connection become known nodes.
+    pub fn touch_connection(&mut self, a: NodeAddr, b: NodeAddr) {
+        if a == b {
+            return;
+        }
+
+        self.node_mut(a);
+        self.node_mut(b);
+
+        let mut connection = KnownConnection::new(a, b);
+        if let Some(known) = self.connections.get(&connection) {
+            connection.first_seen = known.first_seen;
+        }
+        // Connections hash and compare by their addresses only, so this replaces the timestamps.
+        self.connections.replace(connection);
+    }
+
+    /// Restores previously recorded connections.
+    ///
+    /// Connections which are already known keep the earliest `first_seen` and the latest
+    /// `last_seen` timestamp.
+    pub fn restore_connections<'a, I: IntoIterator<Item = &'a KnownConnection>>(
+        &mut self,
+        connections: I,
+    ) {
+        for &connection in connections {
+            if connection.a == connection.b {
+                continue;
+            }
+
+            self.node_mut(connection.a);
+            self.node_mut(connection.b);
+
+            let mut connection = connection;
+            if let Some(known) = self.connections.get(&connection) {
+                connection.first_seen = connection.first_seen.min(known.first_seen);
+                connection.last_seen = connection.last_seen.max(known.last_seen);
+            }
+            self.connections.replace(connection);
+        }
+    }
+
+    /// Saves all known connections to file.
+    pub fn save_connections<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let json = serde_json::to_string(&self.connections)?;
+        fs::write(path, json)
+    }
+
+    /// Loads connections saved with [`KnownNetwork::save_connections`].
+    pub fn load_connections<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let connections: Vec<KnownConnection> = serde_json::from_slice(&fs::read(path)?)?;
+        self.restore_connections(&connections);
+        Ok(())
+    }
+
+    /// Removes the node together with all of its connections.
+    pub fn remove_node(&mut self, addr: &NodeAddr) -> Option<KnownNode> {
+        self.connections
+            .retain(|connection| connection.a != *addr && connection.b != *addr);
+        self.nodes.remove(addr)
+    }
+
+    /// Removes connections which haven't been seen for longer than `ttl`.
+    ///
+    /// Returns the number of removed connections.
+    pub fn prune_connections(&mut self, ttl: Duration) -> usize {
+        self.prune_connections_at(SystemTime::now(), ttl)
+    }
+
+    fn prune_connections_at(&mut self, now: SystemTime, ttl: Duration) -> usize {
+        let before = self.connections.len();
+        self.connections.retain(|connection| {
+            now.duration_since(connection.last_seen).unwrap_or_default() <= ttl
+        });
+        before - self.connections.len()
+    }
+
+    /// Constructs a summary of the current network state.
+    ///
+    /// Only nodes with a successful handshake are included in the connection graph.
+    pub fn summary(&self) -> NetworkSummary {
+        let mut node_addrs: Vec<NodeAddr> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.handshake_successful)
+            .map(|(addr, _)| *addr)
+            .collect();
+        node_addrs.sort_unstable();
+
+        let positions: HashMap<NodeAddr, usize> = node_addrs
+            .iter()
+            .enumerate()
+            .map(|(index, addr)| (*addr, index))
+            .collect();
+
+        let mut nodes_indices: NodesIndices = vec![Vec::new(); node_addrs.len()];
+        for connection in &self.connections {
+            if let (Some(&a), Some(&b)) =
+                (positions.get(&connection.a), positions.get(&connection.b))
+            {
+                nodes_indices[a].push(b);
+                nodes_indices[b].push(a);
+            }
+        }
+        nodes_indices
+            .iter_mut()
+            .for_each(|list| list.sort_unstable());
+
+        let node_records = node_addrs
+            .iter()
+            .map(|addr| {
+                let node = &self.nodes[addr];
+                NodeRecord {
+                    addr: *addr,
+                    network_type: node.network_type.clone(),
+                    user_agent: node.user_agent.clone(),
+                    protocol_version: node.protocol_version,
+                    services: node.services,
+                    start_height: node.start_height,
+                    handshake_latency: node.handshake_latency,
+                    first_seen: node.first_seen,
+                    last_seen: node.last_connected,
+                    connection_failures: node.connection_failures,
+                }
+            })
+            .collect();
+
+        let mut summary = NetworkSummary {
+            num_known_nodes: self.nodes.len(),
+            num_known_connections: self.connections.len(),
+            crawler_runtime: self.started.elapsed(),
+            nodes_indices,
+            node_records,
+            ..Default::default()
+        };
+        summary.derive_from_records();
+        summary
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::addr;
+
+    #[test]
+    fn should_prune_stale_connections() {
+        let mut network = KnownNetwork::new();
+        network.touch_connection(addr(1), addr(2));
+        network.touch_connection(addr(2), addr(1));
+        assert_eq!(network.connections().len(), 1);
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        assert_eq!(
+            network.prune_connections_at(later, Duration::from_secs(120)),
+            0
+        );
+        assert_eq!(
+            network.prune_connections_at(later, Duration::from_secs(30)),
+            1
+        );
+        assert!(network.connections().is_empty());
+        assert_eq!(network.nodes().len(), 2);
+    }
+
+    #[test]
+    fn should_summarize_good_nodes() {
+        let mut network = KnownNetwork::new();
+        network.touch_connection(addr(1), addr(2));
+        network.touch_connection(addr(2), addr(3));
+
+        for last in [1, 2] {
+            let node = network.node_mut(addr(last));
+            node.handshake_successful = true;
+            node.protocol_version = Some(170100);
+            node.user_agent = Some("/MagicBean:5.4.2/".to_owned());
+            node.network_type = NetworkType::Zcash;
+        }
+
+        let summary = network.summary();
+        assert_eq!(summary.num_known_nodes, 3);
+        assert_eq!(summary.num_good_nodes, 2);
+        assert_eq!(summary.num_known_connections, 2);
+        assert_eq!(summary.num_versions, 2);
+        assert_eq!(summary.protocol_versions.get(&170100), Some(&2));
+        assert_eq!(summary.node_addrs, vec![addr(1), addr(2)]);
+        assert_eq!(summary.nodes_indices, vec![vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn should_restore_saved_connections() {
+        let mut network = KnownNetwork::new();
+        network.touch_connection(addr(1), addr(2));
+        network.touch_connection(addr(2), addr(3));
+        let first_seen = network
+            .connections()
+            .get(&KnownConnection::new(addr(1), addr(2)))
+            .unwrap()
+            .first_seen;
+        network.touch_connection(addr(2), addr(1));
+
+        let refreshed = *network
+            .connections()
+            .get(&KnownConnection::new(addr(1), addr(2)))
+            .unwrap();
+        assert_eq!(refreshed.first_seen, first_seen);
+        assert!(refreshed.last_seen >= refreshed.first_seen);
+
+        let path = std::env::temp_dir().join(format!(
+            "ziggurat-known-network-connections-{}.json",
+            std::process::id()
+        ));
+        network.save_connections(&path).unwrap();
+
+        let mut restored = KnownNetwork::new();
+        restored.load_connections(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.connections(), network.connections());
+        let timestamps = |network: &KnownNetwork| {
+            let mut timestamps: Vec<_> = network
+                .connections()
+                .iter()
+                .map(|connection| {
+                    let (a, b) = (
+                        connection.a.min(connection.b),
+                        connection.a.max(connection.b),
+                    );
+                    (a, b, connection.first_seen, connection.last_seen)
+                })
+                .collect();
+            timestamps.sort_unstable();
+            timestamps
+        };
+        assert_eq!(timestamps(&restored), timestamps(&network));
+        assert_eq!(restored.nodes().len(), 3);
+        assert!(restored
+            .connections()
+            .iter()
+            .all(|connection| connection.first_seen <= connection.last_seen));
+    }
+}
@@ -0,0 +1,609 @@
+//! Node filters producing consistent sub-summaries, with a small expression syntax.
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address::NodeAddr,
+    graph::adjacency_sets,
+    network::KnownNetwork,
+    summary::{NetworkSummary, NetworkType},
+};
+
+/// Source of per-node data which isn't stored in a [`NetworkSummary`].
+pub trait NodeMetadata {
+    /// Returns the protocol version reported by the node.
+    fn protocol_version(&self, addr: &NodeAddr) -> Option<u32>;
+    /// Returns the user agent reported by the node.
+    fn user_agent(&self, addr: &NodeAddr) -> Option<&str>;
+}
+
+impl NodeMetadata for KnownNetwork {
+    fn protocol_version(&self, addr: &NodeAddr) -> Option<u32> {
+        self.node(addr).and_then(|node| node.protocol_version)
+    }
+
+    fn user_agent(&self, addr: &NodeAddr) -> Option<&str> {
+        self.node(addr).and_then(|node| node.user_agent.as_deref())
+    }
+}
+
+/// No external metadata, only the summary's own node records are used.
+impl NodeMetadata for () {
+    fn protocol_version(&self, _addr: &NodeAddr) -> Option<u32> {
+        None
+    }
+
+    fn user_agent(&self, _addr: &NodeAddr) -> Option<&str> {
+        None
+    }
+}
+
+/// Comparison operator of a numeric filter.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn test<T: Ord>(self, value: T, reference: T) -> bool {
+        match self {
+            Comparison::Eq => value == reference,
+            Comparison::Ne => value != reference,
+            Comparison::Lt => value < reference,
+            Comparison::Le => value <= reference,
+            Comparison::Gt => value > reference,
+            Comparison::Ge => value >= reference,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+        write!(f, "{operator}")
+    }
+}
+
+/// Address family of a node.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+    TorV3,
+    I2p,
+    Cjdns,
+}
+
+impl FromStr for AddressFamily {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "ipv4" | "v4" | "4" => Ok(AddressFamily::Ipv4),
+            "ipv6" | "v6" | "6" => Ok(AddressFamily::Ipv6),
+            "torv3" | "tor" | "onion" => Ok(AddressFamily::TorV3),
+            "i2p" => Ok(AddressFamily::I2p),
+            "cjdns" => Ok(AddressFamily::Cjdns),
+            _ => Err(format!("invalid address family: {input}")),
+        }
+    }
+}
+
+/// A predicate over the nodes of a summary.
+///
+/// Can be parsed from expressions such as `network == Zcash && degree >= 8`. The supported
+/// fields are `network`, `agent`, `version`, `degree`, `family` and `port`. `network`,
+/// `agent` and `family` support `==` and `!=` only, the rest support all comparisons. Agent
+/// patterns may contain `*` wildcards; values containing spaces or operator characters can be
+/// quoted with `"`. Unknown network names are rejected to catch typos, unless quoted, in which
+/// case they refer to custom networks. Conditions combine with `!`, `&&`, `||` and parentheses,
+/// negations and parentheses nest at most 64 levels deep.
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub enum Filter {
+    /// Nodes of the network type.
+    NetworkType(NetworkType),
+    /// Nodes whose user agent matches the pattern, `*` matches any sequence of characters.
+    UserAgent(String),
+    /// Nodes whose protocol version compares as given. Nodes without a version only match
+    /// `Ne`, as they do a negated network type or user agent filter.
+    ProtocolVersion(Comparison, u32),
+    /// Nodes whose degree in the original summary compares as given.
+    Degree(Comparison, usize),
+    /// Nodes with an address of the family.
+    AddressFamily(AddressFamily),
+    /// Nodes whose port compares as given.
+    Port(Comparison, u16),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+/// Node data a filter is evaluated against.
+struct Node<'a> {
+    addr: NodeAddr,
+    network_type: &'a NetworkType,
+    degree: usize,
+    protocol_version: Option<u32>,
+    user_agent: Option<&'a str>,
+}
+
+impl Filter {
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        Filter::Not(Box::new(self))
+    }
+
+    pub fn and(self, other: Filter) -> Self {
+        Filter::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        Filter::Or(Box::new(self), Box::new(other))
+    }
+
+    fn matches(&self, node: &Node) -> bool {
+        match self {
+            Filter::NetworkType(network_type) => node.network_type == network_type,
+            Filter::UserAgent(pattern) => node
+                .user_agent
+                .is_some_and(|agent| matches_pattern(pattern, agent)),
+            Filter::ProtocolVersion(Comparison::Ne, version) => {
+                node.protocol_version != Some(*version)
+            }
+            Filter::ProtocolVersion(comparison, version) => node
+                .protocol_version
+                .is_some_and(|found| comparison.test(found, *version)),
+            Filter::Degree(comparison, degree) => comparison.test(node.degree, *degree),
+            Filter::AddressFamily(family) => {
+                let found = match node.addr {
+                    NodeAddr::Ip(addr) if addr.socket_addr().is_ipv4() => AddressFamily::Ipv4,
+                    NodeAddr::Ip(_) => AddressFamily::Ipv6,
+                    NodeAddr::TorV3 { .. } => AddressFamily::TorV3,
+                    NodeAddr::I2p { .. } => AddressFamily::I2p,
+                    NodeAddr::Cjdns(_) => AddressFamily::Cjdns,
+                };
+                found == *family
+            }
+            Filter::Port(comparison, port) => comparison.test(node.addr.port(), *port),
+            Filter::Not(filter) => !filter.matches(node),
+            Filter::And(a, b) => a.matches(node) && b.matches(node),
+            Filter::Or(a, b) => a.matches(node) || b.matches(node),
+        }
+    }
+}
+
+/// Matches `text` against a pattern in which `*` matches any sequence of characters.
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard in the pattern.
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[derive(PartialEq, Clone, Debug)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Comparison(Comparison),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' | '|' => {
+                if chars.next() != Some(c) {
+                    return Err(format!("expected `{c}{c}`"));
+                }
+                if c == '&' {
+                    Token::And
+                } else {
+                    Token::Or
+                }
+            }
+            '=' | '!' | '<' | '>' => {
+                let equals = chars.next_if_eq(&'=').is_some();
+                match (c, equals) {
+                    ('=', true) => Token::Comparison(Comparison::Eq),
+                    ('!', true) => Token::Comparison(Comparison::Ne),
+                    ('<', true) => Token::Comparison(Comparison::Le),
+                    ('>', true) => Token::Comparison(Comparison::Ge),
+                    ('<', false) => Token::Comparison(Comparison::Lt),
+                    ('>', false) => Token::Comparison(Comparison::Gt),
+                    ('!', false) => Token::Not,
+                    _ => return Err("expected `==`".to_owned()),
+                }
+            }
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quoted value".to_owned()),
+                    }
+                }
+                Token::Quoted(word)
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()&|=!<>\"".contains(*c))
+                {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Maximum nesting of negations and parentheses, which keeps the recursion of the parser
+/// bounded.
+const MAX_DEPTH: usize = 64;
+
+/// Recursive descent parser of filter expressions.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.position) == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.eat(&Token::Or) {
+            filter = filter.or(self.and()?);
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.unary()?;
+        while self.eat(&Token::And) {
+            filter = filter.and(self.unary()?);
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, String> {
+        if self.eat(&Token::Not) {
+            return Ok(self.nested(Self::unary)?.not());
+        }
+        if self.eat(&Token::Open) {
+            let filter = self.nested(Self::or)?;
+            if !self.eat(&Token::Close) {
+                return Err("expected `)`".to_owned());
+            }
+            return Ok(filter);
+        }
+        self.condition()
+    }
+
+    /// Parses a nested expression, unless the nesting is too deep.
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Filter, String>) -> Result<Filter, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("expression nested deeper than {MAX_DEPTH} levels"));
+        }
+        self.depth += 1;
+        let filter = parse(self);
+        self.depth -= 1;
+        filter
+    }
+
+    fn condition(&mut self) -> Result<Filter, String> {
+        let Some(Token::Word(field)) = self.next() else {
+            return Err("expected a field name".to_owned());
+        };
+        let Some(Token::Comparison(comparison)) = self.next() else {
+            return Err(format!("expected a comparison after `{field}`"));
+        };
+        let (value, quoted) = match self.next() {
+            Some(Token::Word(value)) => (value, false),
+            Some(Token::Quoted(value)) => (value, true),
+            _ => return Err(format!("expected a value after `{field} {comparison}`")),
+        };
+
+        let equality = |filter: Filter| match comparison {
+            Comparison::Eq => Ok(filter),
+            Comparison::Ne => Ok(filter.not()),
+            _ => Err(format!("`{field}` doesn't support `{comparison}`")),
+        };
+
+        match field.to_ascii_lowercase().as_str() {
+            "network" if quoted => equality(Filter::NetworkType(value.parse()?)),
+            "network" => equality(Filter::NetworkType(
+                NetworkType::known(&value).ok_or_else(|| format!("unknown network: {value}"))?,
+            )),
+            "agent" => equality(Filter::UserAgent(value)),
+            "family" => equality(Filter::AddressFamily(value.parse()?)),
+            "version" => Ok(Filter::ProtocolVersion(
+                comparison,
+                parse_number(&field, &value)?,
+            )),
+            "degree" => Ok(Filter::Degree(comparison, parse_number(&field, &value)?)),
+            "port" => Ok(Filter::Port(comparison, parse_number(&field, &value)?)),
+            _ => Err(format!("unknown field: {field}")),
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(field: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value of `{field}`: {value}"))
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+            depth: 0,
+        };
+
+        let filter = parser.or()?;
+        if parser.position < parser.tokens.len() {
+            return Err("unexpected input after the expression".to_owned());
+        }
+
+        Ok(filter)
+    }
+}
+
+impl NetworkSummary {
+    /// Returns a sub-summary of the nodes matching the filter.
+    ///
+    /// The sub-summary is re-indexed and its counts only cover the matching nodes and the
+    /// connections between them. Versions and user agents are taken from `metadata`, or from
+    /// the summary's node records when `metadata` doesn't have them.
+    pub fn filter<M: NodeMetadata + ?Sized>(&self, filter: &Filter, metadata: &M) -> Self {
+        let adjacency = adjacency_sets(&self.nodes_indices);
+        let unknown = NetworkType::Unknown;
+
+        let kept: Vec<usize> = (0..self.node_addrs.len())
+            .filter(|&index| {
+                let (protocol_version, user_agent) = self.node_version(index, metadata);
+                filter.matches(&Node {
+                    addr: self.node_addrs[index],
+                    network_type: self.node_network_types.get(index).unwrap_or(&unknown),
+                    degree: adjacency.get(index).map_or(0, |set| set.len()),
+                    protocol_version,
+                    user_agent,
+                })
+            })
+            .collect();
+
+        let positions: HashMap<usize, usize> = kept
+            .iter()
+            .enumerate()
+            .map(|(position, index)| (*index, position))
+            .collect();
+
+        let mut summary = NetworkSummary {
+            num_known_nodes: kept.len(),
+            num_good_nodes: kept.len(),
+            crawler_runtime: self.crawler_runtime,
+            ..Default::default()
+        };
+
+        for index in &kept {
+            let addr = self.node_addrs[*index];
+            let mut neighbours: Vec<usize> = adjacency
+                .get(*index)
+                .into_iter()
+                .flatten()
+                .filter_map(|neighbour| positions.get(neighbour).copied())
+                .collect();
+            neighbours.sort_unstable();
+            summary.num_known_connections += neighbours.len();
+
+            let (protocol_version, user_agent) = self.node_version(*index, metadata);
+            if let Some(version) = protocol_version {
+                summary.num_versions += 1;
+                *summary.protocol_versions.entry(version).or_default() += 1;
+            }
+            if let Some(agent) = user_agent {
+                *summary.user_agents.entry(agent.to_owned()).or_default() += 1;
+            }
+            if self.has_node_records() {
+                summary.node_records.push(self.node_records[*index].clone());
+            }
+
+            summary.node_addrs.push(addr);
+            summary.node_network_types.push(
+                self.node_network_types
+                    .get(*index)
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+            summary.nodes_indices.push(neighbours);
+        }
+        summary.num_known_connections /= 2;
+
+        summary
+    }
+
+    /// Returns the protocol version and user agent of the node at the index, taken from
+    /// `metadata` or else from the node's record.
+    pub(crate) fn node_version<'a, M: NodeMetadata + ?Sized>(
+        &'a self,
+        index: usize,
+        metadata: &'a M,
+    ) -> (Option<u32>, Option<&'a str>) {
+        let addr = &self.node_addrs[index];
+        let record = self
+            .node_records
+            .get(index)
+            .filter(|record| record.addr == *addr);
+
+        (
+            metadata
+                .protocol_version(addr)
+                .or_else(|| record.and_then(|record| record.protocol_version)),
+            metadata
+                .user_agent(addr)
+                .or_else(|| record.and_then(|record| record.user_agent.as_deref())),
+        )
+    }
+
+    /// Parses the filter expression and returns a sub-summary of the matching nodes.
+    pub fn query<M: NodeMetadata + ?Sized>(
+        &self,
+        expression: &str,
+        metadata: &M,
+    ) -> Result<Self, String> {
+        Ok(self.filter(&expression.parse()?, metadata))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::addr;
+
+    #[test]
+    fn should_parse_expressions() {
+        assert_eq!(
+            "network == Zcash && degree >= 8".parse::<Filter>(),
+            Ok(Filter::NetworkType(NetworkType::Zcash).and(Filter::Degree(Comparison::Ge, 8)))
+        );
+        assert_eq!(
+            "!(port != 8233 || family == ipv6) && agent == \"/Magic Bean:*\"".parse::<Filter>(),
+            Ok(Filter::Port(Comparison::Ne, 8233)
+                .or(Filter::AddressFamily(AddressFamily::Ipv6))
+                .not()
+                .and(Filter::UserAgent("/Magic Bean:*".to_owned())))
+        );
+        assert!("network < Zcash".parse::<Filter>().is_err());
+        assert!("network == Zcsh".parse::<Filter>().is_err());
+        assert_eq!(
+            "network == \"Kusama\"".parse::<Filter>(),
+            Ok(Filter::NetworkType(NetworkType::custom("Kusama")))
+        );
+        assert!("degree >= eight".parse::<Filter>().is_err());
+        assert!("(version == 1".parse::<Filter>().is_err());
+        assert!("colour == red".parse::<Filter>().is_err());
+
+        let nested = |depth: usize| format!("{}port == 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(nested(MAX_DEPTH).parse::<Filter>().is_ok());
+        assert!(nested(MAX_DEPTH + 1).parse::<Filter>().is_err());
+        assert!(format!("{}port == 1", "!".repeat(100_000))
+            .parse::<Filter>()
+            .is_err());
+    }
+
+    #[test]
+    fn should_filter_nodes() {
+        let mut network = KnownNetwork::new();
+        for last in 1..=4 {
+            let node = network.node_mut(addr(last));
+            node.handshake_successful = true;
+            node.network_type = if last == 4 {
+                NetworkType::Bitcoin
+            } else {
+                NetworkType::Zcash
+            };
+            node.protocol_version = Some(170100);
+            node.user_agent = Some(format!("/MagicBean:5.{last}.0/"));
+        }
+        // Star around node 1.
+        for last in 2..=4 {
+            network.touch_connection(addr(1), addr(last));
+        }
+        let summary = network.summary();
+
+        let zcash = summary.query("network == zcash", &network).unwrap();
+        assert_eq!(zcash.node_addrs, vec![addr(1), addr(2), addr(3)]);
+        assert_eq!(zcash.nodes_indices, vec![vec![1, 2], vec![0], vec![0]]);
+        assert_eq!(zcash.num_known_connections, 2);
+        assert_eq!(zcash.protocol_versions[&170100], 3);
+        assert!(zcash.validate().is_empty());
+
+        let leaves = summary
+            .query("degree == 1 && agent == /MagicBean:5.*", &network)
+            .unwrap();
+        assert_eq!(leaves.node_addrs, vec![addr(2), addr(3), addr(4)]);
+
+        // User agents come from the summary's own node records.
+        let leaves = summary.query("degree < 2 && !agent == *5.4*", &()).unwrap();
+        assert_eq!(leaves.node_addrs, vec![addr(2), addr(3)]);
+        assert_eq!(leaves.nodes_indices, vec![Vec::<usize>::new(); 2]);
+        assert_eq!(leaves.user_agents.len(), 2);
+        assert_eq!(leaves.node_records.len(), 2);
+
+        // Nodes without a version match `!=` like they match a negated `agent ==`.
+        let mut summary = summary;
+        summary.node_records[3].protocol_version = None;
+        let other = summary.query("version != 170100", &()).unwrap();
+        assert_eq!(other.node_addrs, vec![addr(4)]);
+        assert_eq!(
+            other.node_addrs,
+            summary.query("!version == 170100", &()).unwrap().node_addrs
+        );
+        assert!(summary
+            .query("version < 170100", &())
+            .unwrap()
+            .node_addrs
+            .is_empty());
+    }
+}
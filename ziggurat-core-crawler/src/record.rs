@@ -0,0 +1,156 @@
+//! Per-node records carried by network summaries.
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address::NodeAddr,
+    summary::{NetworkSummary, NetworkType},
+};
+
+/// Everything known about a single crawled node.
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub struct NodeRecord {
+    /// Address of the node.
+    pub addr: NodeAddr,
+    /// Network the node belongs to.
+    pub network_type: NetworkType,
+    /// Software version reported by the node.
+    pub user_agent: Option<String>,
+    /// Protocol version reported by the node.
+    pub protocol_version: Option<u32>,
+    /// Service flags advertised by the node.
+    pub services: Option<u64>,
+    /// Block height advertised by the node during the handshake.
+    pub start_height: Option<u64>,
+    /// Time it took to complete the handshake.
+    pub handshake_latency: Option<Duration>,
+    /// The time the node was first discovered.
+    pub first_seen: Option<SystemTime>,
+    /// The time of the last successful handshake with the node.
+    pub last_seen: Option<SystemTime>,
+    /// Number of failed attempts since the last complete crawl of the node.
+    pub connection_failures: u8,
+}
+
+impl NodeRecord {
+    /// Creates an empty record of the node.
+    pub fn new(addr: NodeAddr) -> Self {
+        Self {
+            addr,
+            network_type: NetworkType::Unknown,
+            user_agent: None,
+            protocol_version: None,
+            services: None,
+            start_height: None,
+            handshake_latency: None,
+            first_seen: None,
+            last_seen: None,
+            connection_failures: 0,
+        }
+    }
+
+    /// Combines another record of the same node into this one, e.g. one made from a different
+    /// vantage point.
+    ///
+    /// Missing fields are taken from the other record, the earliest `first_seen`, the latest
+    /// `last_seen`, the lowest latency and the lowest failure count are kept.
+    pub fn merge(&mut self, other: &NodeRecord) {
+        if self.network_type == NetworkType::Unknown {
+            self.network_type = other.network_type.clone();
+        }
+        if self.user_agent.is_none() {
+            self.user_agent = other.user_agent.clone();
+        }
+        self.protocol_version = self.protocol_version.or(other.protocol_version);
+        self.services = self.services.or(other.services);
+        self.start_height = self.start_height.max(other.start_height);
+        self.handshake_latency = min_some(self.handshake_latency, other.handshake_latency);
+        self.first_seen = min_some(self.first_seen, other.first_seen);
+        self.last_seen = self.last_seen.max(other.last_seen);
+        self.connection_failures = self.connection_failures.min(other.connection_failures);
+    }
+}
+
+/// Returns the lower of the values which are present.
+fn min_some<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl NetworkSummary {
+    /// Returns `true` if the summary carries a record for every node.
+    pub fn has_node_records(&self) -> bool {
+        !self.node_records.is_empty() && self.node_records.len() == self.node_addrs.len()
+    }
+
+    /// Recomputes node addresses, network types, protocol versions and user agents from the
+    /// node records. Summaries without records are left untouched.
+    ///
+    /// The connection graph isn't changed, so `nodes_indices` must correspond to the records.
+    pub fn derive_from_records(&mut self) {
+        if self.node_records.is_empty() {
+            return;
+        }
+
+        self.node_addrs.clear();
+        self.node_network_types.clear();
+        self.protocol_versions.clear();
+        self.user_agents.clear();
+        self.num_versions = 0;
+
+        for record in &self.node_records {
+            self.node_addrs.push(record.addr);
+            self.node_network_types.push(record.network_type.clone());
+            if let Some(version) = record.protocol_version {
+                self.num_versions += 1;
+                *self.protocol_versions.entry(version).or_default() += 1;
+            }
+            if let Some(user_agent) = &record.user_agent {
+                *self.user_agents.entry(user_agent.clone()).or_default() += 1;
+            }
+        }
+
+        self.num_good_nodes = self.node_records.len();
+        self.num_known_nodes = self.num_known_nodes.max(self.num_good_nodes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::addr;
+
+    #[test]
+    fn should_derive_aggregates_from_records() {
+        let mut records: Vec<NodeRecord> =
+            (1..=3).map(|last| NodeRecord::new(addr(last))).collect();
+        records[0].protocol_version = Some(170100);
+        records[0].user_agent = Some("/MagicBean:5.4.2/".to_owned());
+        records[1].protocol_version = Some(170100);
+        records[2].network_type = NetworkType::Zcash;
+
+        let mut summary = NetworkSummary {
+            num_known_connections: 2,
+            node_records: records.clone(),
+            nodes_indices: vec![vec![1], vec![0, 2], vec![1]],
+            ..Default::default()
+        };
+        summary.derive_from_records();
+
+        assert!(summary.has_node_records());
+        assert_eq!(summary.node_addrs[2], records[2].addr);
+        assert_eq!(summary.node_network_types[2], NetworkType::Zcash);
+        assert_eq!(summary.protocol_versions[&170100], 2);
+        assert_eq!(summary.user_agents["/MagicBean:5.4.2/"], 1);
+        assert_eq!(summary.num_versions, 2);
+        assert!(summary.validate().is_empty());
+
+        let mut merged = records[2].clone();
+        merged.merge(&records[0]);
+        assert_eq!(merged.protocol_version, Some(170100));
+        assert_eq!(merged.network_type, NetworkType::Zcash);
+    }
+}
@@ -0,0 +1,284 @@
+//! Resilience analysis of the crawled network topology.
+use std::cmp;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph::{adjacency_sets, sorted_neighbours},
+    summary::{NetworkSummary, NodesIndices},
+};
+
+/// Marks nodes which weren't visited yet.
+const UNVISITED: usize = usize::MAX;
+
+/// Describes how well the network withstands removal of its nodes and connections.
+///
+/// Node indices correspond to [`NetworkSummary::node_addrs`].
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct ResilienceReport {
+    /// Nodes whose removal splits their connected component.
+    pub articulation_points: Vec<usize>,
+    /// Connections whose removal splits their connected component.
+    pub bridges: Vec<(usize, usize)>,
+    /// Core number of each node, i.e. the largest `k` such that the node belongs to the k-core.
+    pub core_numbers: Vec<usize>,
+    /// Size of the largest connected component after removing 0, 1, 2... nodes in descending
+    /// degree order.
+    pub targeted_removal: Vec<usize>,
+    /// Size of the largest connected component after removing 0, 1, 2... randomly chosen nodes.
+    pub random_removal: Vec<usize>,
+}
+
+impl ResilienceReport {
+    /// Analyses the given adjacency list, random removal order is determined by `seed`.
+    pub fn new(indices: &NodesIndices, seed: u64) -> Self {
+        let neighbours = sorted_neighbours(&adjacency_sets(indices));
+        let (articulation_points, bridges) = cut_points(&neighbours);
+
+        let mut targeted_order: Vec<usize> = (0..neighbours.len()).collect();
+        targeted_order.sort_by_key(|node| cmp::Reverse(neighbours[*node].len()));
+
+        let mut random_order: Vec<usize> = (0..neighbours.len()).collect();
+        random_order.shuffle(&mut StdRng::seed_from_u64(seed));
+
+        Self {
+            articulation_points,
+            bridges,
+            core_numbers: core_numbers(&neighbours),
+            targeted_removal: removal_curve(&neighbours, &targeted_order),
+            random_removal: removal_curve(&neighbours, &random_order),
+        }
+    }
+
+    /// Returns the largest `k` for which the network has a non-empty k-core.
+    pub fn degeneracy(&self) -> usize {
+        self.core_numbers.iter().copied().max().unwrap_or_default()
+    }
+}
+
+impl NetworkSummary {
+    /// Analyses resilience of the crawled network, random removal order is determined by `seed`.
+    pub fn resilience(&self, seed: u64) -> ResilienceReport {
+        ResilienceReport::new(&self.nodes_indices, seed)
+    }
+}
+
+/// Finds articulation points and bridges using an iterative version of Tarjan's algorithm.
+fn cut_points(neighbours: &[Vec<usize>]) -> (Vec<usize>, Vec<(usize, usize)>) {
+    let n = neighbours.len();
+    let mut discovered = vec![UNVISITED; n];
+    let mut low = vec![0; n];
+    let mut is_articulation = vec![false; n];
+    let mut bridges = Vec::new();
+    let mut timer = 0;
+
+    for root in 0..n {
+        if discovered[root] != UNVISITED {
+            continue;
+        }
+
+        discovered[root] = timer;
+        low[root] = timer;
+        timer += 1;
+        let mut root_children = 0;
+        // Entries: (node, parent, position of the next neighbour to visit).
+        let mut stack = vec![(root, UNVISITED, 0)];
+
+        while let Some(&(node, parent, position)) = stack.last() {
+            if let Some(&next) = neighbours[node].get(position) {
+                if let Some(top) = stack.last_mut() {
+                    top.2 += 1;
+                }
+
+                if discovered[next] == UNVISITED {
+                    discovered[next] = timer;
+                    low[next] = timer;
+                    timer += 1;
+                    if node == root {
+                        root_children += 1;
+                    }
+                    stack.push((next, node, 0));
+                } else if next != parent {
+                    low[node] = low[node].min(discovered[next]);
+                }
+                continue;
+            }
+
+            stack.pop();
+            if parent == UNVISITED {
+                continue;
+            }
+
+            low[parent] = low[parent].min(low[node]);
+            if low[node] > discovered[parent] {
+                bridges.push((parent.min(node), parent.max(node)));
+            }
+            if parent != root && low[node] >= discovered[parent] {
+                is_articulation[parent] = true;
+            }
+        }
+
+        if root_children > 1 {
+            is_articulation[root] = true;
+        }
+    }
+
+    bridges.sort_unstable();
+    let articulation_points = (0..n).filter(|node| is_articulation[*node]).collect();
+
+    (articulation_points, bridges)
+}
+
+/// Computes core numbers using the Batagelj-Zaversnik algorithm.
+fn core_numbers(neighbours: &[Vec<usize>]) -> Vec<usize> {
+    let n = neighbours.len();
+    let mut degrees: Vec<usize> = neighbours.iter().map(Vec::len).collect();
+    let max_degree = degrees.iter().copied().max().unwrap_or_default();
+
+    // Bucket sort the nodes by their degree.
+    let mut bin_starts = vec![0; max_degree + 1];
+    for degree in &degrees {
+        bin_starts[*degree] += 1;
+    }
+    let mut start = 0;
+    for bin in bin_starts.iter_mut() {
+        let size = *bin;
+        *bin = start;
+        start += size;
+    }
+
+    let mut order = vec![0; n];
+    let mut positions = vec![0; n];
+    let mut next_free = bin_starts.clone();
+    for node in 0..n {
+        positions[node] = next_free[degrees[node]];
+        order[positions[node]] = node;
+        next_free[degrees[node]] += 1;
+    }
+
+    for i in 0..n {
+        let node = order[i];
+        for &neighbour in &neighbours[node] {
+            if degrees[neighbour] > degrees[node] {
+                // Move the neighbour to the start of its bin and shrink the bin.
+                let degree = degrees[neighbour];
+                let first_position = bin_starts[degree];
+                let first = order[first_position];
+                if first != neighbour {
+                    order.swap(first_position, positions[neighbour]);
+                    positions[first] = positions[neighbour];
+                    positions[neighbour] = first_position;
+                }
+                bin_starts[degree] += 1;
+                degrees[neighbour] -= 1;
+            }
+        }
+    }
+
+    degrees
+}
+
+/// Computes the size of the largest connected component after each removal in the given order.
+///
+/// The result starts with the size for the intact network and ends with zero. Nodes are added
+/// back in reverse order so that a single union-find pass is enough.
+fn removal_curve(neighbours: &[Vec<usize>], order: &[usize]) -> Vec<usize> {
+    let n = neighbours.len();
+    let mut parents: Vec<usize> = (0..n).collect();
+    let mut sizes = vec![1; n];
+    let mut present = vec![false; n];
+    let mut largest = 0;
+    let mut curve = vec![0; order.len() + 1];
+
+    fn find(parents: &mut [usize], mut node: usize) -> usize {
+        while parents[node] != node {
+            parents[node] = parents[parents[node]];
+            node = parents[node];
+        }
+        node
+    }
+
+    for (step, &node) in order.iter().enumerate().rev() {
+        present[node] = true;
+        largest = largest.max(1);
+
+        for &neighbour in &neighbours[node] {
+            if !present[neighbour] {
+                continue;
+            }
+
+            let (a, b) = (find(&mut parents, node), find(&mut parents, neighbour));
+            if a != b {
+                let (small, big) = if sizes[a] < sizes[b] { (a, b) } else { (b, a) };
+                parents[small] = big;
+                sizes[big] += sizes[small];
+                largest = largest.max(sizes[big]);
+            }
+        }
+
+        curve[step] = largest;
+    }
+
+    curve
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn two_triangles() -> NodesIndices {
+        // Triangles 0-1-2 and 3-4-5 joined by the 2-3 connection.
+        vec![
+            vec![1, 2],
+            vec![0, 2],
+            vec![0, 1, 3],
+            vec![2, 4, 5],
+            vec![3, 5],
+            vec![3, 4],
+        ]
+    }
+
+    #[test]
+    fn should_find_articulation_points_and_bridges() {
+        let report = ResilienceReport::new(&two_triangles(), 0);
+
+        assert_eq!(report.articulation_points, vec![2, 3]);
+        assert_eq!(report.bridges, vec![(2, 3)]);
+        assert_eq!(report.core_numbers, vec![2; 6]);
+        assert_eq!(report.degeneracy(), 2);
+    }
+
+    #[test]
+    fn should_compute_core_numbers_of_star_with_clique() {
+        // Clique 0-1-2-3 with leaves 4 and 5 attached to 0.
+        let indices = vec![
+            vec![1, 2, 3, 4, 5],
+            vec![0, 2, 3],
+            vec![0, 1, 3],
+            vec![0, 1, 2],
+            vec![0],
+            vec![0],
+        ];
+
+        assert_eq!(
+            ResilienceReport::new(&indices, 0).core_numbers,
+            vec![3, 3, 3, 3, 1, 1]
+        );
+    }
+
+    #[test]
+    fn should_simulate_node_removal() {
+        let report = ResilienceReport::new(&two_triangles(), 7);
+
+        // Removing 2 first leaves the 3-4-5 triangle, removing 3 next leaves two pairs.
+        assert_eq!(report.targeted_removal, vec![6, 3, 2, 2, 2, 1, 0]);
+        assert_eq!(report.random_removal.len(), 7);
+        assert_eq!(report.random_removal[0], 6);
+        assert_eq!(report.random_removal[6], 0);
+        assert_eq!(
+            report.random_removal,
+            ResilienceReport::new(&two_triangles(), 7).random_removal
+        );
+    }
+}
@@ -1,35 +1,162 @@
-use std::{cmp, collections::HashMap, fmt, fs, net::SocketAddr, path::Path, time::Duration};
+use std::{
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, fs,
+    hash::Hash,
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{address::NodeAddr, record::NodeRecord};
 
 // This struct contains a list of connection indices for each node
 // It is equivalent to an adjacency or degree matrix, expressed in a compact form
 pub type NodesIndices = Vec<Vec<usize>>;
 
 /// Enumaration of known networks that node can belong to.
-#[derive(Default, PartialEq, Clone, Deserialize, Serialize, Debug, Copy)]
+///
+/// Network types are (de)serialized as their names, so that they round-trip through
+/// [`fmt::Display`] and [`FromStr`].
+#[derive(Default, PartialEq, Eq, Hash, Clone, Debug)]
 pub enum NetworkType {
     #[default]
     Unknown,
-    Zcash,
+    Aleo,
+    Algorand,
+    Bitcoin,
+    Celo,
+    Ethereum,
+    Polkadot,
     Ripple,
+    Zcash,
+    /// An unrecognised name, as looked up through `From<&str>`.
     Invalid,
+    /// A network not known to this crate, constructed with [`NetworkType::custom`].
+    Custom(CustomNetwork),
+}
+
+/// Name of a network not known to this crate.
+///
+/// Can only be constructed through [`NetworkType::custom`], so it never holds the name of a
+/// known network.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct CustomNetwork(String);
+
+impl NetworkType {
+    /// Networks with a dedicated variant, used for name lookups.
+    const NAMED: [NetworkType; 10] = [
+        NetworkType::Unknown,
+        NetworkType::Aleo,
+        NetworkType::Algorand,
+        NetworkType::Bitcoin,
+        NetworkType::Celo,
+        NetworkType::Ethereum,
+        NetworkType::Polkadot,
+        NetworkType::Ripple,
+        NetworkType::Zcash,
+        NetworkType::Invalid,
+    ];
+
+    /// Creates a network type for the given name.
+    ///
+    /// Names of known networks (compared case-insensitively) resolve to their dedicated variant,
+    /// so a custom network can never shadow a known one. Other names are kept as given.
+    pub fn custom(name: &str) -> NetworkType {
+        Self::known(name).unwrap_or_else(|| NetworkType::Custom(CustomNetwork(name.to_owned())))
+    }
+
+    /// Returns the known network with the given name, compared case-insensitively.
+    pub fn known(name: &str) -> Option<NetworkType> {
+        Self::NAMED
+            .into_iter()
+            .find(|network| network.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the name of the network.
+    pub fn name(&self) -> &str {
+        match self {
+            NetworkType::Unknown => "Unknown",
+            NetworkType::Aleo => "Aleo",
+            NetworkType::Algorand => "Algorand",
+            NetworkType::Bitcoin => "Bitcoin",
+            NetworkType::Celo => "Celo",
+            NetworkType::Ethereum => "Ethereum",
+            NetworkType::Polkadot => "Polkadot",
+            NetworkType::Ripple => "Ripple",
+            NetworkType::Zcash => "Zcash",
+            NetworkType::Invalid => "Invalid",
+            NetworkType::Custom(CustomNetwork(name)) => name,
+        }
+    }
+}
+
+impl fmt::Display for NetworkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
+/// Looks up a known network by its exact name, unrecognised names map to
+/// [`NetworkType::Invalid`]. Custom networks are created with [`NetworkType::custom`] or
+/// [`FromStr`].
 impl From<&str> for NetworkType {
     fn from(input: &str) -> NetworkType {
-        match input {
-            "Unknown" => NetworkType::Unknown,
-            "Zcash" => NetworkType::Zcash,
-            "Ripple" => NetworkType::Ripple,
-            _ => NetworkType::Invalid,
+        NetworkType::NAMED
+            .into_iter()
+            .find(|network| network.name() == input)
+            .unwrap_or(NetworkType::Invalid)
+    }
+}
+
+impl FromStr for NetworkType {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let name = input.trim();
+        if name.is_empty() {
+            return Err("network name can't be empty".to_owned());
         }
+        if name.chars().any(|c| c.is_control()) {
+            return Err(format!("invalid network name: {name:?}"));
+        }
+
+        Ok(NetworkType::custom(name))
     }
 }
 
+impl Serialize for NetworkType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for NetworkType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Version of the persisted [`NetworkSummary`] format, bumped whenever its fields change.
+///
+/// Summaries persisted in older formats are upgraded by [`crate::migration`].
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Version of summaries persisted before the version was recorded.
+fn legacy_schema_version() -> u32 {
+    1
+}
+
 /// Contains stats about crawled network.
-#[derive(Default, Clone, Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct NetworkSummary {
+    /// Version of the format, [`SCHEMA_VERSION`] for summaries created by this crate and `1`
+    /// for legacy ones deserialized directly, without being upgraded.
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
     /// Total number of nodes discovered.
     pub num_known_nodes: usize,
     /// Number of nodes that a crawler was able to connect to.
@@ -45,11 +172,36 @@ pub struct NetworkSummary {
     /// Crawler's runtime.
     pub crawler_runtime: Duration,
     /// Addresses of good nodes.
-    pub node_addrs: Vec<SocketAddr>,
+    pub node_addrs: Vec<NodeAddr>,
     /// Network types of good nodes. Indexes correspond to `node_addrs` and `node_indices`.
+    /// Empty in legacy summaries deserialized directly, see [`NetworkSummary::repair`].
+    #[serde(default)]
     pub node_network_types: Vec<NetworkType>,
     /// Unidirected connections graph.
     pub nodes_indices: NodesIndices,
+    /// Records of good nodes, empty if the summary was created without them.
+    /// Indexes correspond to `node_addrs`.
+    #[serde(default)]
+    pub node_records: Vec<NodeRecord>,
+}
+
+impl Default for NetworkSummary {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            num_known_nodes: 0,
+            num_good_nodes: 0,
+            num_known_connections: 0,
+            num_versions: 0,
+            protocol_versions: HashMap::new(),
+            user_agents: HashMap::new(),
+            crawler_runtime: Duration::ZERO,
+            node_addrs: Vec::new(),
+            node_network_types: Vec::new(),
+            nodes_indices: Vec::new(),
+            node_records: Vec::new(),
+        }
+    }
 }
 
 impl NetworkSummary {
@@ -60,22 +212,23 @@ impl NetworkSummary {
     }
 }
 
-impl fmt::Display for NetworkSummary {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fn print_hashmap<T: fmt::Display>(
-            f: &mut fmt::Formatter<'_>,
-            counts: &HashMap<T, usize>,
-        ) -> fmt::Result {
-            let mut vec: Vec<(&T, &usize)> = counts.iter().collect();
-            vec.sort_by_key(|(_, count)| cmp::Reverse(*count));
+/// Prints counts sorted from the most to the least common item.
+pub(crate) fn print_hashmap<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    counts: &HashMap<T, usize>,
+) -> fmt::Result {
+    let mut vec: Vec<(&T, &usize)> = counts.iter().collect();
+    vec.sort_by_key(|(_, count)| cmp::Reverse(*count));
 
-            for (item, count) in &vec {
-                writeln!(f, "{item}: {count}")?;
-            }
+    for (item, count) in &vec {
+        writeln!(f, "{item}: {count}")?;
+    }
 
-            Ok(())
-        }
+    Ok(())
+}
 
+impl fmt::Display for NetworkSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Network summary:\n")?;
         writeln!(f, "Found a total of {} node(s)", self.num_known_nodes)?;
         writeln!(f, "Managed to connect to {} node(s)", self.num_good_nodes)?;
@@ -95,6 +248,15 @@ impl fmt::Display for NetworkSummary {
         writeln!(f, "\nUser agents:")?;
         print_hashmap(f, &self.user_agents)?;
 
+        if !self.node_network_types.is_empty() {
+            let mut network_types = HashMap::new();
+            for network in &self.node_network_types {
+                *network_types.entry(network).or_default() += 1;
+            }
+            writeln!(f, "\nNetwork types:")?;
+            print_hashmap(f, &network_types)?;
+        }
+
         writeln!(
             f,
             "\nCrawler ran for a total of {} minutes",
@@ -104,3 +266,268 @@ impl fmt::Display for NetworkSummary {
         Ok(())
     }
 }
+
+/// Change of a single count between two crawls.
+#[derive(Default, PartialEq, Eq, Clone, Copy, Deserialize, Serialize, Debug)]
+pub struct CountChange {
+    /// Count in the older summary.
+    pub old: usize,
+    /// Count in the newer summary.
+    pub new: usize,
+}
+
+impl CountChange {
+    /// Returns the signed difference between the new and the old count.
+    pub fn delta(&self) -> i64 {
+        self.new as i64 - self.old as i64
+    }
+}
+
+/// Contains changes between two crawls of the same network.
+#[derive(Default, Clone, Deserialize, Serialize, Debug)]
+pub struct SummaryDiff {
+    /// Nodes present only in the newer summary.
+    pub joined_nodes: Vec<NodeAddr>,
+    /// Nodes present only in the older summary.
+    pub left_nodes: Vec<NodeAddr>,
+    /// Connections present only in the newer summary.
+    pub added_connections: Vec<(NodeAddr, NodeAddr)>,
+    /// Connections present only in the older summary.
+    pub removed_connections: Vec<(NodeAddr, NodeAddr)>,
+    /// Map: Version number -> changed number of nodes that reported this version.
+    pub protocol_versions: BTreeMap<u32, CountChange>,
+    /// Map: User agent -> changed number of nodes that reported this user agent.
+    pub user_agents: BTreeMap<String, CountChange>,
+    /// Change in the number of nodes that a crawler was able to connect to.
+    pub num_good_nodes: CountChange,
+}
+
+impl SummaryDiff {
+    /// Compares an older summary with a newer one.
+    pub fn new(old: &NetworkSummary, new: &NetworkSummary) -> Self {
+        let old_nodes: BTreeSet<NodeAddr> = old.node_addrs.iter().copied().collect();
+        let new_nodes: BTreeSet<NodeAddr> = new.node_addrs.iter().copied().collect();
+        let old_connections = old.connections();
+        let new_connections = new.connections();
+
+        Self {
+            joined_nodes: new_nodes.difference(&old_nodes).copied().collect(),
+            left_nodes: old_nodes.difference(&new_nodes).copied().collect(),
+            added_connections: new_connections
+                .difference(&old_connections)
+                .copied()
+                .collect(),
+            removed_connections: old_connections
+                .difference(&new_connections)
+                .copied()
+                .collect(),
+            protocol_versions: count_changes(&old.protocol_versions, &new.protocol_versions),
+            user_agents: count_changes(&old.user_agents, &new.user_agents),
+            num_good_nodes: CountChange {
+                old: old.num_good_nodes,
+                new: new.num_good_nodes,
+            },
+        }
+    }
+
+    /// Returns true if both summaries describe the same network state.
+    pub fn is_empty(&self) -> bool {
+        self.joined_nodes.is_empty()
+            && self.left_nodes.is_empty()
+            && self.added_connections.is_empty()
+            && self.removed_connections.is_empty()
+            && self.protocol_versions.is_empty()
+            && self.user_agents.is_empty()
+            && self.num_good_nodes.delta() == 0
+    }
+}
+
+impl NetworkSummary {
+    /// Compares this summary with a newer one.
+    pub fn diff(&self, newer: &NetworkSummary) -> SummaryDiff {
+        SummaryDiff::new(self, newer)
+    }
+
+    /// Returns all connections as address pairs, each ordered so that the smaller address goes first.
+    fn connections(&self) -> BTreeSet<(NodeAddr, NodeAddr)> {
+        let mut connections = BTreeSet::new();
+
+        for (node, neighbours) in self.nodes_indices.iter().enumerate() {
+            for &neighbour in neighbours {
+                if let (Some(&a), Some(&b)) =
+                    (self.node_addrs.get(node), self.node_addrs.get(neighbour))
+                {
+                    if a != b {
+                        connections.insert((a.min(b), a.max(b)));
+                    }
+                }
+            }
+        }
+
+        connections
+    }
+}
+
+/// Collects all keys whose counts differ between two maps.
+fn count_changes<T: Clone + Eq + Hash + Ord>(
+    old: &HashMap<T, usize>,
+    new: &HashMap<T, usize>,
+) -> BTreeMap<T, CountChange> {
+    old.keys()
+        .chain(new.keys())
+        .filter_map(|key| {
+            let change = CountChange {
+                old: old.get(key).copied().unwrap_or_default(),
+                new: new.get(key).copied().unwrap_or_default(),
+            };
+            (change.delta() != 0).then(|| (key.clone(), change))
+        })
+        .collect()
+}
+
+impl fmt::Display for SummaryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn print_changes<T: fmt::Display>(
+            f: &mut fmt::Formatter<'_>,
+            changes: &BTreeMap<T, CountChange>,
+        ) -> fmt::Result {
+            let mut vec: Vec<(&T, &CountChange)> = changes.iter().collect();
+            vec.sort_by_key(|(_, change)| cmp::Reverse(change.delta().abs()));
+
+            for (item, change) in &vec {
+                writeln!(
+                    f,
+                    "{item}: {} -> {} ({:+})",
+                    change.old,
+                    change.new,
+                    change.delta()
+                )?;
+            }
+
+            Ok(())
+        }
+
+        writeln!(f, "Network summary diff:\n")?;
+        writeln!(
+            f,
+            "Managed to connect to {} -> {} node(s) ({:+})",
+            self.num_good_nodes.old,
+            self.num_good_nodes.new,
+            self.num_good_nodes.delta()
+        )?;
+        writeln!(f, "{} node(s) joined", self.joined_nodes.len())?;
+        writeln!(f, "{} node(s) left", self.left_nodes.len())?;
+        writeln!(
+            f,
+            "{} connection(s) added, {} connection(s) removed",
+            self.added_connections.len(),
+            self.removed_connections.len()
+        )?;
+
+        writeln!(f, "\nProtocol versions:")?;
+        print_changes(f, &self.protocol_versions)?;
+        writeln!(f, "\nUser agents:")?;
+        print_changes(f, &self.user_agents)?;
+
+        writeln!(f, "\nJoined nodes:")?;
+        for addr in &self.joined_nodes {
+            writeln!(f, "{addr}")?;
+        }
+        writeln!(f, "\nLeft nodes:")?;
+        for addr in &self.left_nodes {
+            writeln!(f, "{addr}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::addr;
+
+    #[test]
+    fn should_report_changes_between_crawls() {
+        let old = NetworkSummary {
+            num_good_nodes: 3,
+            protocol_versions: HashMap::from([(170100, 3)]),
+            user_agents: HashMap::from([("/MagicBean:5.4.2/".to_owned(), 3)]),
+            node_addrs: vec![addr(1), addr(2), addr(3)],
+            nodes_indices: vec![vec![1], vec![0, 2], vec![1]],
+            ..Default::default()
+        };
+        // Node indices are intentionally shuffled to make sure the diff is keyed by address.
+        let new = NetworkSummary {
+            num_good_nodes: 3,
+            protocol_versions: HashMap::from([(170100, 2), (170110, 1)]),
+            user_agents: HashMap::from([("/MagicBean:5.4.2/".to_owned(), 3)]),
+            node_addrs: vec![addr(4), addr(2), addr(1)],
+            nodes_indices: vec![vec![2], vec![2], vec![0, 1]],
+            ..Default::default()
+        };
+
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.joined_nodes, vec![addr(4)]);
+        assert_eq!(diff.left_nodes, vec![addr(3)]);
+        assert_eq!(diff.added_connections, vec![(addr(1), addr(4))]);
+        assert_eq!(diff.removed_connections, vec![(addr(2), addr(3))]);
+        assert_eq!(
+            diff.protocol_versions.get(&170110),
+            Some(&CountChange { old: 0, new: 1 })
+        );
+        assert_eq!(diff.protocol_versions.get(&170100).unwrap().delta(), -1);
+        assert!(diff.user_agents.is_empty());
+        assert_eq!(diff.num_good_nodes.delta(), 0);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn should_produce_empty_diff_for_same_summary() {
+        let summary = NetworkSummary {
+            node_addrs: vec![addr(1), addr(2)],
+            nodes_indices: vec![vec![1], vec![0]],
+            ..Default::default()
+        };
+
+        assert!(summary.diff(&summary).is_empty());
+    }
+
+    #[test]
+    fn should_round_trip_network_types() {
+        for network in NetworkType::NAMED
+            .into_iter()
+            .chain([NetworkType::custom("Kusama")])
+        {
+            assert_eq!(network.to_string().parse::<NetworkType>(), Ok(network));
+        }
+
+        assert_eq!("zcash".parse::<NetworkType>(), Ok(NetworkType::Zcash));
+        assert_eq!(NetworkType::custom("RIPPLE"), NetworkType::Ripple);
+        assert_eq!(NetworkType::from("Zcash"), NetworkType::Zcash);
+        assert_eq!(NetworkType::from("zcash"), NetworkType::Invalid);
+        assert_eq!(NetworkType::from("Kusama"), NetworkType::Invalid);
+        assert_eq!(NetworkType::custom("KUSAMA").name(), "KUSAMA");
+        assert_eq!(NetworkType::known("Kusama"), None);
+        assert!("".parse::<NetworkType>().is_err());
+    }
+
+    #[test]
+    fn should_deserialize_network_types_by_name() {
+        let json = r#"["Zcash","Ripple","Invalid","Unknown","Kusama"]"#;
+        let networks: Vec<NetworkType> = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            networks,
+            vec![
+                NetworkType::Zcash,
+                NetworkType::Ripple,
+                NetworkType::Invalid,
+                NetworkType::Unknown,
+                NetworkType::custom("Kusama"),
+            ]
+        );
+        assert_eq!(serde_json::to_string(&networks).unwrap(), json);
+    }
+}
@@ -0,0 +1,7 @@
+//! Fixtures shared by the unit tests.
+use crate::address::NodeAddr;
+
+/// Returns the address `1.2.3.<last>:8233`.
+pub(crate) fn addr(last: u8) -> NodeAddr {
+    NodeAddr::from(([1, 2, 3, last], 8233))
+}
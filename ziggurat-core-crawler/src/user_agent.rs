@@ -0,0 +1,483 @@
+//! Parsing of user agents and reports on client version adoption.
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::summary::NetworkSummary;
+
+/// Semantic version of a client.
+///
+/// Missing minor or patch components are treated as zero. Build metadata, e.g. `+linux` in
+/// `5.4.0+linux`, is ignored as it doesn't affect precedence.
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Deserialize, Serialize)]
+pub struct ClientVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Components following the patch one, e.g. `[1]` for `0.9.3.1`, without trailing zeros.
+    #[serde(default)]
+    pub extra: Vec<u64>,
+    /// Pre-release suffix, e.g. `rc1` for `5.4.0-rc1`.
+    pub suffix: Option<String>,
+}
+
+/// Part of a pre-release suffix, numeric parts compare numerically and precede textual ones.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Identifier<'a> {
+    Number(u64),
+    Text(&'a str),
+}
+
+/// Splits a pre-release suffix into identifiers at separators and between digits and letters,
+/// so that `rc10` follows `rc9`.
+fn identifiers(suffix: &str) -> Vec<Identifier<'_>> {
+    let mut identifiers = Vec::new();
+    for part in suffix.split(['.', '-', '_']) {
+        let mut rest = part;
+        while let Some(first) = rest.chars().next() {
+            let end = rest
+                .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let (identifier, tail) = rest.split_at(end);
+            identifiers.push(match identifier.parse() {
+                Ok(number) => Identifier::Number(number),
+                Err(_) => Identifier::Text(identifier),
+            });
+            rest = tail;
+        }
+    }
+    identifiers
+}
+
+impl ClientVersion {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            extra: Vec::new(),
+            suffix: None,
+        }
+    }
+}
+
+impl Ord for ClientVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch, &self.extra)
+            .cmp(&(other.major, other.minor, other.patch, &other.extra))
+            .then_with(|| match (&self.suffix, &other.suffix) {
+                // A pre-release precedes the release itself.
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                // Suffixes which only differ in leading zeros or separators are ordered as
+                // strings to stay consistent with equality.
+                (Some(a), Some(b)) => identifiers(a).cmp(&identifiers(b)).then_with(|| a.cmp(b)),
+            })
+    }
+}
+
+impl PartialOrd for ClientVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for ClientVersion {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let input = input.strip_prefix(['v', 'V']).unwrap_or(input);
+
+        let end = input
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(input.len());
+        let (numbers, suffix) = input.split_at(end);
+
+        // Empty components, e.g. in `5..4` or `5.4.`, fail to parse.
+        let mut parts = numbers.split('.');
+        let mut next = || -> Result<u64, String> {
+            parts
+                .next()
+                .map(|part| {
+                    part.parse()
+                        .map_err(|_| format!("invalid version: {input}"))
+                })
+                .unwrap_or(Ok(0))
+        };
+
+        if numbers.is_empty() {
+            return Err(format!("invalid version: {input}"));
+        }
+
+        let (major, minor, patch) = (next()?, next()?, next()?);
+        let mut extra = Vec::new();
+        for part in parts {
+            extra.push(
+                part.parse()
+                    .map_err(|_| format!("invalid version: {input}"))?,
+            );
+        }
+        while extra.last() == Some(&0) {
+            extra.pop();
+        }
+
+        // Build metadata follows a `+`, possibly without any pre-release suffix.
+        let pre_release = suffix.split('+').next().unwrap_or_default();
+        let version = ClientVersion {
+            major,
+            minor,
+            patch,
+            extra,
+            suffix: Some(pre_release.trim_start_matches(['-', '.', '_']))
+                .filter(|suffix| !suffix.is_empty())
+                .map(str::to_owned),
+        };
+
+        Ok(version)
+    }
+}
+
+impl fmt::Display for ClientVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        for component in &self.extra {
+            write!(f, ".{component}")?;
+        }
+        if let Some(suffix) = &self.suffix {
+            write!(f, "-{suffix}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Major and minor components of a version, by which reports group releases.
+///
+/// Serialized as `major.minor`, so that it can key JSON maps.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct MajorMinor {
+    pub major: u64,
+    pub minor: u64,
+}
+
+impl From<&ClientVersion> for MajorMinor {
+    fn from(version: &ClientVersion) -> Self {
+        Self {
+            major: version.major,
+            minor: version.minor,
+        }
+    }
+}
+
+impl FromStr for MajorMinor {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        input
+            .split_once('.')
+            .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+            .map(|(major, minor)| Self { major, minor })
+            .ok_or_else(|| format!("invalid version: {input}"))
+    }
+}
+
+impl TryFrom<String> for MajorMinor {
+    type Error = String;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        input.parse()
+    }
+}
+
+impl From<MajorMinor> for String {
+    fn from(version: MajorMinor) -> Self {
+        version.to_string()
+    }
+}
+
+impl fmt::Display for MajorMinor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// A user agent split into its components.
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub struct UserAgent {
+    /// Name of the client, e.g. `MagicBean` or `rippled`.
+    pub client: String,
+    /// Version of the client, if it could be parsed.
+    pub version: Option<ClientVersion>,
+    /// Comments and additional components, e.g. `Knots:20210629` in `/Satoshi:0.21.0/Knots:20210629/`.
+    pub tags: Vec<String>,
+}
+
+impl UserAgent {
+    /// Parses a user agent, both BIP14 (`/Name:1.2.3(comment)/`) and `name-1.2.3` styles are
+    /// supported.
+    ///
+    /// Unrecognised user agents are kept whole as the client name.
+    pub fn parse(input: &str) -> Self {
+        let input = input.trim();
+
+        if input.starts_with('/') {
+            let mut components = input
+                .split('/')
+                .map(str::trim)
+                .filter(|component| !component.is_empty());
+
+            if let Some(first) = components.next() {
+                let (main, mut tags) = split_comments(first);
+                let mut agent = parse_component(main);
+                tags.extend(components.map(str::to_owned));
+                agent.tags.append(&mut tags);
+                return agent;
+            }
+        }
+
+        let (main, tags) = split_comments(input);
+        let mut agent = parse_component(main);
+        agent.tags = tags;
+        agent
+    }
+}
+
+impl fmt::Display for UserAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.client)?;
+        if let Some(version) = &self.version {
+            write!(f, " {version}")?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " ({})", self.tags.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `name:1.2.3(comment; other)` into the main part and its comments.
+fn split_comments(component: &str) -> (&str, Vec<String>) {
+    match component.split_once('(') {
+        Some((main, comments)) => (
+            main.trim(),
+            comments
+                .trim_end_matches(')')
+                .split(';')
+                .map(str::trim)
+                .filter(|comment| !comment.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        ),
+        None => (component, Vec::new()),
+    }
+}
+
+/// Parses a single `name:version` (or `name-version`, `name/version`, `name version`) component.
+fn parse_component(component: &str) -> UserAgent {
+    // The version starts at the first separator followed by a digit (or `v` and a digit).
+    let split = component.char_indices().find(|(index, c)| {
+        let rest = &component[index + c.len_utf8()..];
+        let rest = rest.strip_prefix(['v', 'V']).unwrap_or(rest);
+        [':', '-', ' ', '/', '_'].contains(c) && rest.starts_with(|c: char| c.is_ascii_digit())
+    });
+
+    if let Some((index, separator)) = split {
+        if let Ok(version) = component[index + separator.len_utf8()..].parse() {
+            return UserAgent {
+                client: component[..index].trim().to_owned(),
+                version: Some(version),
+                tags: Vec::new(),
+            };
+        }
+    }
+
+    UserAgent {
+        client: component.trim_end_matches(':').to_owned(),
+        version: None,
+        tags: Vec::new(),
+    }
+}
+
+/// Aggregated client and version adoption of a network.
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+pub struct UserAgentReport {
+    /// Total number of nodes which reported a user agent.
+    pub num_nodes: usize,
+    /// Map: Client -> number of nodes running it.
+    pub clients: BTreeMap<String, usize>,
+    /// Map: Client -> (Major.minor version -> number of nodes running it).
+    pub versions: BTreeMap<String, BTreeMap<MajorMinor, usize>>,
+    /// Map: User agent -> number of nodes running a version older than the required minimum.
+    pub outdated: BTreeMap<String, usize>,
+    /// Number of nodes running a version older than the required minimum.
+    pub num_outdated: usize,
+}
+
+impl UserAgentReport {
+    /// Builds a report from user agent counts.
+    ///
+    /// `minimum_versions` maps client names to the oldest version considered up to date. Clients
+    /// without a minimum, and user agents without a parseable version, are never flagged.
+    pub fn new(
+        user_agents: &HashMap<String, usize>,
+        minimum_versions: &HashMap<String, ClientVersion>,
+    ) -> Self {
+        let mut report = Self::default();
+
+        for (raw, count) in user_agents {
+            let agent = UserAgent::parse(raw);
+            report.num_nodes += count;
+            *report.clients.entry(agent.client.clone()).or_default() += count;
+
+            let Some(version) = &agent.version else {
+                continue;
+            };
+            *report
+                .versions
+                .entry(agent.client.clone())
+                .or_default()
+                .entry(MajorMinor::from(version))
+                .or_default() += count;
+
+            if matches!(minimum_versions.get(&agent.client), Some(minimum) if version < minimum) {
+                *report.outdated.entry(raw.clone()).or_default() += count;
+                report.num_outdated += count;
+            }
+        }
+
+        report
+    }
+
+    /// Returns the share of nodes running an outdated version.
+    pub fn outdated_share(&self) -> f64 {
+        if self.num_nodes == 0 {
+            0.0
+        } else {
+            self.num_outdated as f64 / self.num_nodes as f64
+        }
+    }
+}
+
+impl NetworkSummary {
+    /// Builds a client and version adoption report of the crawled network.
+    pub fn user_agent_report(
+        &self,
+        minimum_versions: &HashMap<String, ClientVersion>,
+    ) -> UserAgentReport {
+        UserAgentReport::new(&self.user_agents, minimum_versions)
+    }
+}
+
+impl fmt::Display for UserAgentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "User agent report:\n")?;
+        writeln!(f, "{} node(s) reported a user agent", self.num_nodes)?;
+        writeln!(
+            f,
+            "{} node(s) run an outdated version ({:.1}%)",
+            self.num_outdated,
+            self.outdated_share() * 100.0
+        )?;
+
+        writeln!(f, "\nClients:")?;
+        for (client, count) in &self.clients {
+            writeln!(f, "{client}: {count}")?;
+            if let Some(versions) = self.versions.get(client) {
+                for (version, count) in versions {
+                    writeln!(f, "  {version}: {count}")?;
+                }
+            }
+        }
+
+        writeln!(f, "\nOutdated user agents:")?;
+        for (agent, count) in &self.outdated {
+            writeln!(f, "{agent}: {count}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_parse_user_agents() {
+        let agent = UserAgent::parse("/MagicBean:5.4.2/");
+        assert_eq!(agent.client, "MagicBean");
+        assert_eq!(agent.version, Some(ClientVersion::new(5, 4, 2)));
+
+        let agent = UserAgent::parse("rippled-1.10.0");
+        assert_eq!(agent.client, "rippled");
+        assert_eq!(agent.version, Some(ClientVersion::new(1, 10, 0)));
+
+        let agent = UserAgent::parse("/Zebra:1.0.0-rc.4(mainnet; linux)/Knots:2021/");
+        assert_eq!(agent.client, "Zebra");
+        assert_eq!(agent.version.unwrap().suffix.as_deref(), Some("rc.4"));
+        assert_eq!(agent.tags, vec!["mainnet", "linux", "Knots:2021"]);
+
+        let agent = UserAgent::parse("weird agent");
+        assert_eq!(agent.client, "weird agent");
+        assert_eq!(agent.version, None);
+    }
+
+    #[test]
+    fn should_order_versions() {
+        let release: ClientVersion = "5.4.0".parse().unwrap();
+        let candidate: ClientVersion = "5.4.0-rc1".parse().unwrap();
+
+        assert!(candidate < release);
+        assert!(release < "5.10".parse().unwrap());
+        assert!("v4.9.9".parse::<ClientVersion>().unwrap() < candidate);
+
+        let parse = |input: &str| input.parse::<ClientVersion>().unwrap();
+        assert!(parse("5.4.0-rc9") < parse("5.4.0-rc10"));
+        assert!(parse("5.4.0-rc.2") < parse("5.4.0-rc.10"));
+        assert!(parse("5.4.0-1") < parse("5.4.0-alpha"));
+        assert_eq!(parse("5.4.0+linux"), release);
+        assert_eq!(parse("5.4.0-rc1+linux"), candidate);
+        assert!(release < parse("5.4.0.1"));
+        assert_eq!(parse("5.4.0.0"), release);
+        assert_eq!(parse("0.9.3.1").to_string(), "0.9.3.1");
+        assert!("5..4".parse::<ClientVersion>().is_err());
+        assert!("5.4.".parse::<ClientVersion>().is_err());
+        assert!(".5.4".parse::<ClientVersion>().is_err());
+    }
+
+    #[test]
+    fn should_flag_outdated_versions() {
+        let user_agents = HashMap::from([
+            ("/MagicBean:5.4.2/".to_owned(), 6),
+            ("/MagicBean:5.3.0/".to_owned(), 3),
+            ("/MagicBean:5.10.1/".to_owned(), 2),
+            ("/Zebra:1.0.0/".to_owned(), 1),
+        ]);
+        let minimum = HashMap::from([("MagicBean".to_owned(), ClientVersion::new(5, 4, 0))]);
+
+        let report = UserAgentReport::new(&user_agents, &minimum);
+
+        assert_eq!(report.num_nodes, 12);
+        assert_eq!(report.clients["MagicBean"], 11);
+        let versions: Vec<String> = report.versions["MagicBean"]
+            .keys()
+            .map(|version| version.to_string())
+            .collect();
+        assert_eq!(versions, vec!["5.3", "5.4", "5.10"]);
+        assert_eq!(report.versions["MagicBean"][&"5.3".parse().unwrap()], 3);
+        assert_eq!(report.outdated["/MagicBean:5.3.0/"], 3);
+        assert_eq!(report.num_outdated, 3);
+        assert!((report.outdated_share() - 0.25).abs() < 1e-9);
+
+        let json = serde_json::to_string(&report).unwrap();
+        let report: UserAgentReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(report.versions["MagicBean"].len(), 3);
+    }
+}
@@ -0,0 +1,304 @@
+//! Consistency checks of network summaries loaded from untrusted sources.
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address::NodeAddr,
+    summary::{NetworkSummary, NetworkType},
+};
+
+/// A consistency violation found in a [`NetworkSummary`].
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub enum Violation {
+    /// `node_network_types` doesn't have an entry for every node.
+    NetworkTypesLength { expected: usize, found: usize },
+    /// `nodes_indices` doesn't have an entry for every node.
+    IndicesLength { expected: usize, found: usize },
+    /// `node_records` is neither empty nor has an entry for every node.
+    RecordsLength { expected: usize, found: usize },
+    /// The node's record belongs to a different address.
+    RecordAddress { node: usize },
+    /// A node is connected to an index which doesn't exist.
+    IndexOutOfRange { node: usize, index: usize },
+    /// A node is connected to itself.
+    SelfConnection { node: usize },
+    /// A node lists the same connection more than once.
+    DuplicateConnection { node: usize, index: usize },
+    /// A connection is listed by only one of its sides.
+    AsymmetricConnection { node: usize, index: usize },
+    /// The same address belongs to more than one node.
+    DuplicateAddress {
+        addr: NodeAddr,
+        first: usize,
+        duplicate: usize,
+    },
+    /// `num_good_nodes` doesn't match the number of node addresses.
+    GoodNodesCount { expected: usize, found: usize },
+    /// `num_known_nodes` is lower than the number of good nodes.
+    KnownNodesCount { minimum: usize, found: usize },
+    /// `num_known_connections` is lower than the number of connections in the graph.
+    KnownConnectionsCount { minimum: usize, found: usize },
+    /// `num_versions` doesn't match the total of `protocol_versions`.
+    VersionsCount { expected: usize, found: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::NetworkTypesLength { expected, found } => {
+                write!(f, "expected {expected} network type(s), found {found}")
+            }
+            Violation::IndicesLength { expected, found } => {
+                write!(f, "expected {expected} adjacency list(s), found {found}")
+            }
+            Violation::RecordsLength { expected, found } => {
+                write!(f, "expected {expected} node record(s), found {found}")
+            }
+            Violation::RecordAddress { node } => {
+                write!(f, "record of node {node} belongs to a different address")
+            }
+            Violation::IndexOutOfRange { node, index } => {
+                write!(f, "node {node} is connected to non-existent node {index}")
+            }
+            Violation::SelfConnection { node } => write!(f, "node {node} is connected to itself"),
+            Violation::DuplicateConnection { node, index } => {
+                write!(
+                    f,
+                    "node {node} lists the connection to {index} more than once"
+                )
+            }
+            Violation::AsymmetricConnection { node, index } => write!(
+                f,
+                "node {node} is connected to {index}, but not the other way round"
+            ),
+            Violation::DuplicateAddress {
+                addr,
+                first,
+                duplicate,
+            } => write!(f, "address {addr} belongs to nodes {first} and {duplicate}"),
+            Violation::GoodNodesCount { expected, found } => {
+                write!(f, "expected {expected} good node(s), found {found}")
+            }
+            Violation::KnownNodesCount { minimum, found } => {
+                write!(
+                    f,
+                    "expected at least {minimum} known node(s), found {found}"
+                )
+            }
+            Violation::KnownConnectionsCount { minimum, found } => write!(
+                f,
+                "expected at least {minimum} known connection(s), found {found}"
+            ),
+            Violation::VersionsCount { expected, found } => {
+                write!(
+                    f,
+                    "expected {expected} node(s) with a version, found {found}"
+                )
+            }
+        }
+    }
+}
+
+impl NetworkSummary {
+    /// Checks that the summary is internally consistent.
+    ///
+    /// Returns an empty list for a consistent summary.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let num_nodes = self.node_addrs.len();
+
+        if self.node_network_types.len() != num_nodes {
+            violations.push(Violation::NetworkTypesLength {
+                expected: num_nodes,
+                found: self.node_network_types.len(),
+            });
+        }
+        if self.nodes_indices.len() != num_nodes {
+            violations.push(Violation::IndicesLength {
+                expected: num_nodes,
+                found: self.nodes_indices.len(),
+            });
+        }
+
+        if !self.node_records.is_empty() {
+            if self.node_records.len() != num_nodes {
+                violations.push(Violation::RecordsLength {
+                    expected: num_nodes,
+                    found: self.node_records.len(),
+                });
+            }
+            for (node, (addr, record)) in self.node_addrs.iter().zip(&self.node_records).enumerate()
+            {
+                if record.addr != *addr {
+                    violations.push(Violation::RecordAddress { node });
+                }
+            }
+        }
+
+        let mut first_nodes: HashMap<NodeAddr, usize> = HashMap::new();
+        for (node, addr) in self.node_addrs.iter().enumerate() {
+            let first = *first_nodes.entry(*addr).or_insert(node);
+            if first != node {
+                violations.push(Violation::DuplicateAddress {
+                    addr: *addr,
+                    first,
+                    duplicate: node,
+                });
+            }
+        }
+
+        let mut connections = BTreeSet::new();
+        for (node, neighbours) in self.nodes_indices.iter().enumerate() {
+            let mut seen = BTreeSet::new();
+            for &index in neighbours {
+                if index >= num_nodes || node >= num_nodes {
+                    violations.push(Violation::IndexOutOfRange { node, index });
+                } else if index == node {
+                    violations.push(Violation::SelfConnection { node });
+                } else if !seen.insert(index) {
+                    violations.push(Violation::DuplicateConnection { node, index });
+                } else {
+                    connections.insert((node.min(index), node.max(index)));
+                    if !self
+                        .nodes_indices
+                        .get(index)
+                        .is_some_and(|list| list.contains(&node))
+                    {
+                        violations.push(Violation::AsymmetricConnection { node, index });
+                    }
+                }
+            }
+        }
+
+        if self.num_good_nodes != num_nodes {
+            violations.push(Violation::GoodNodesCount {
+                expected: num_nodes,
+                found: self.num_good_nodes,
+            });
+        }
+        if self.num_known_nodes < num_nodes {
+            violations.push(Violation::KnownNodesCount {
+                minimum: num_nodes,
+                found: self.num_known_nodes,
+            });
+        }
+        if self.num_known_connections < connections.len() {
+            violations.push(Violation::KnownConnectionsCount {
+                minimum: connections.len(),
+                found: self.num_known_connections,
+            });
+        }
+
+        let num_versions = self.protocol_versions.values().sum();
+        if self.num_versions != num_versions {
+            violations.push(Violation::VersionsCount {
+                expected: num_versions,
+                found: self.num_versions,
+            });
+        }
+
+        violations
+    }
+
+    /// Fixes all violations which can be fixed without additional data.
+    ///
+    /// Connections are made symmetric and deduplicated, connections to non-existent nodes and
+    /// self connections are dropped, missing network types are set to unknown and the counts
+    /// are made to agree with the data. Node records which don't line up with the node
+    /// addresses are dropped. Duplicate addresses are left untouched.
+    ///
+    /// Returns the violations found before the repair.
+    pub fn repair(&mut self) -> Vec<Violation> {
+        let violations = self.validate();
+        let num_nodes = self.node_addrs.len();
+
+        self.node_network_types
+            .resize(num_nodes, NetworkType::Unknown);
+        self.nodes_indices.resize(num_nodes, Vec::new());
+        if !self.node_records.is_empty()
+            && (self.node_records.len() != num_nodes
+                || self
+                    .node_addrs
+                    .iter()
+                    .zip(&self.node_records)
+                    .any(|(addr, record)| record.addr != *addr))
+        {
+            self.node_records.clear();
+        }
+
+        let mut adjacency = vec![BTreeSet::new(); num_nodes];
+        for (node, neighbours) in self.nodes_indices.iter().enumerate() {
+            for &index in neighbours {
+                if index < num_nodes && index != node {
+                    adjacency[node].insert(index);
+                    adjacency[index].insert(node);
+                }
+            }
+        }
+        let num_connections = adjacency.iter().map(BTreeSet::len).sum::<usize>() / 2;
+        self.nodes_indices = adjacency
+            .into_iter()
+            .map(|set| set.into_iter().collect())
+            .collect();
+
+        self.num_good_nodes = num_nodes;
+        self.num_known_nodes = self.num_known_nodes.max(num_nodes);
+        self.num_known_connections = self.num_known_connections.max(num_connections);
+        self.num_versions = self.protocol_versions.values().sum();
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::addr;
+
+    #[test]
+    fn should_report_violations() {
+        let summary = NetworkSummary {
+            num_known_nodes: 3,
+            num_good_nodes: 3,
+            num_known_connections: 2,
+            node_addrs: vec![addr(1), addr(2), addr(3)],
+            node_network_types: vec![NetworkType::Zcash; 2],
+            nodes_indices: vec![vec![1, 1, 5], vec![0, 2], vec![]],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            summary.validate(),
+            vec![
+                Violation::NetworkTypesLength {
+                    expected: 3,
+                    found: 2
+                },
+                Violation::DuplicateConnection { node: 0, index: 1 },
+                Violation::IndexOutOfRange { node: 0, index: 5 },
+                Violation::AsymmetricConnection { node: 1, index: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_repair_summary() {
+        let mut summary = NetworkSummary {
+            num_versions: 1,
+            node_addrs: vec![addr(1), addr(2), addr(3)],
+            nodes_indices: vec![vec![0, 1, 7], vec![2]],
+            ..Default::default()
+        };
+
+        assert!(!summary.repair().is_empty());
+        assert!(summary.validate().is_empty());
+        assert_eq!(summary.nodes_indices, vec![vec![1], vec![0, 2], vec![1]]);
+        assert_eq!(summary.num_known_connections, 2);
+        assert_eq!(summary.num_versions, 0);
+        assert_eq!(summary.node_network_types, vec![NetworkType::Unknown; 3]);
+    }
+}